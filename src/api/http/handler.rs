@@ -1,22 +1,41 @@
-use axum::extract::{Path, State};
+use axum::extract::{Path, Query, State};
+use axum::http::header::CONTENT_TYPE;
 use axum::response::IntoResponse;
 use axum::Json;
-use axum_auth::AuthBasic;
+use axum_auth::{AuthBasic, AuthBearer};
 use sea_orm::{
-    AccessMode, DatabaseConnection, DatabaseTransaction, DbErr, IsolationLevel, TransactionTrait,
+    AccessMode, ActiveEnum, DatabaseConnection, DatabaseTransaction, DbErr, IsolationLevel,
+    TransactionTrait,
 };
 use serde::Deserialize;
+use subtle::ConstantTimeEq;
 use tracing::{instrument, Level};
 
 use crate::api::{Attachment, MsgPak};
 use crate::entity;
-use crate::entity::variant::{DetectedGuest, PublicPlayer};
+use crate::entity::region::Region;
+use crate::entity::variant::{
+    DetectedGuest, NodeView, PlayerProfile, PublicAlliance, PublicPlayer, Ranked,
+};
 use crate::err::{ApiError, OperationError};
-use entropy_base::grid::{navi, Node, NodeID, ALLOWED_NAVI};
+use crate::world::heatmap::{self, Shade};
+use entropy_base::grid::{navi, NodeID};
 
 use super::AppState;
-use crate::entity::guest::Model as Guest;
+use crate::entity::alliance::Model as Alliance;
+use crate::entity::battery::{Access as BatteryAccess, Model as Battery};
+use crate::entity::claim::{Mode as ClaimMode, Model as Claim};
+use crate::entity::engine::Model as Engine;
+use crate::entity::event::Model as WorldEvent;
+use crate::entity::guest::{get_walk_cost, Model as Guest};
+use crate::entity::invite::Model as Invite;
+use crate::entity::leaderboard::Rank;
+use crate::entity::message::Model as Message;
+use crate::entity::offer::{Goods, Kind as OfferKind, Model as Offer};
 use crate::entity::player::Model as Player;
+use crate::entity::season::Model as Season;
+use crate::entity::siphon::Model as Siphon;
+use crate::entity::visit::Model as Visit;
 
 #[derive(Debug, Deserialize)]
 pub struct PlayerAuth {
@@ -36,7 +55,7 @@ pub struct WalkCommand {
 }
 impl WalkCommand {
     pub fn verify(&self) -> Result<(), OperationError> {
-        if ALLOWED_NAVI.contains(&self.to) {
+        if get_walk_cost(self.to).is_some() {
             Ok(())
         } else {
             Err(OperationError::DirectionNotAllowed(self.to))
@@ -50,6 +69,116 @@ pub struct HeatCommand {
     pub energy: i64,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct CoolCommand {
+    pub at: usize,
+    pub energy: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BuildEngineCommand {
+    pub cell: usize,
+    pub other: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CollectEngineCommand {
+    pub engine: i32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BuildBatteryCommand {
+    pub capacity: i64,
+    pub access: BatteryAccess,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BatteryCommand {
+    pub battery: i32,
+    pub energy: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SiphonCommand {
+    pub target: i32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ClaimCommand {
+    pub mode: ClaimMode,
+    pub duration: i64,
+    /// energy staked, the cost of the duration if omitted, more to contest a claim
+    pub stake: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateAllianceCommand {
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AllianceMemberCommand {
+    pub player: i32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AcceptAllianceCommand {
+    pub alliance: i32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GiveCommand {
+    pub target: i32,
+    pub energy: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PostMessageCommand {
+    pub content: String,
+    /// broadcast to nodes this far away, the node only if omitted
+    #[serde(default)]
+    pub radius: i16,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListMessageQuery {
+    /// only messages after this id, for polling
+    #[serde(default)]
+    pub after: i32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PostOfferCommand {
+    #[serde(flatten)]
+    pub goods: Goods,
+    pub price: i64,
+    /// seconds the offer stays open
+    pub expire: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OfferCommand {
+    pub offer: i32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListOfferQuery {
+    pub kind: Option<OfferKind>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LeaderboardQuery {
+    #[serde(default)]
+    pub by: Rank,
+    #[serde(default)]
+    pub page: u64,
+    #[serde(default = "default_page_size")]
+    pub size: u64,
+}
+fn default_page_size() -> u64 {
+    20
+}
+
 #[derive(Debug, Deserialize)]
 pub struct HarvestCommand {
     pub at: usize,
@@ -70,10 +199,8 @@ pub async fn ping(State(state): State<AppState>) -> Result<&'static str, ApiErro
 pub async fn get_player_public(
     State(state): State<AppState>,
     Path(id): Path<i32>,
-) -> Result<Json<PublicPlayer>, ApiError> {
-    Ok(Json(
-        entity::get_exact_player_public(&state.conn, id).await?,
-    ))
+) -> Result<Json<PlayerProfile>, ApiError> {
+    Ok(Json(entity::get_player_profile(&state.conn, id).await?))
 }
 
 #[instrument(skip(state), ret(level = Level::DEBUG), err(level = Level::INFO))]
@@ -126,39 +253,54 @@ pub async fn spawn_guest(
 pub async fn get_node(
     State(state): State<AppState>,
     Path((x, y)): Path<(i16, i16)>,
-) -> Result<Json<Node>, ApiError> {
+    auth: Option<AuthBasic>,
+) -> Result<Json<NodeView>, ApiError> {
+    let player = verify_optional_header(auth)?;
     let txn = begin_txn(&state.conn).await?;
-    let n = entity::get_node(&txn, NodeID::from_xy(x, y)).await?;
+    let n = entity::view_node(&txn, player, NodeID::from_xy(x, y)).await?;
     txn.commit().await?;
-    Ok(Json(n.into()))
+    Ok(Json(n))
 }
 
+/// Terrain of the node along with the raw cells.
+const TERRAIN_HEADER: &str = "x-terrain";
+
 #[instrument(skip(state), err(level = Level::INFO))]
 pub async fn get_node_bytes(
     State(state): State<AppState>,
     Path((x, y)): Path<(i16, i16)>,
-) -> Result<Attachment<Vec<u8>>, ApiError> {
+    auth: Option<AuthBasic>,
+) -> Result<impl IntoResponse, ApiError> {
+    let player = verify_optional_header(auth)?;
     let txn = begin_txn(&state.conn).await?;
-    let n = entity::get_node(&txn, NodeID::from_xy(x, y)).await?;
+    let n = entity::get_visible_node(&txn, player, NodeID::from_xy(x, y)).await?;
     txn.commit().await?;
-    Ok(Attachment {
-        raw: n.data,
-        file_name: format!("{x}-{y}.bin"),
-    })
+    Ok((
+        [(TERRAIN_HEADER, n.terrain.to_value())],
+        Attachment {
+            raw: n.data,
+            file_name: format!("{x}-{y}.bin"),
+        },
+    ))
 }
 
 #[instrument(skip(state), err(level = Level::INFO))]
 pub async fn get_node_msgpak(
     State(state): State<AppState>,
     Path((x, y)): Path<(i16, i16)>,
+    auth: Option<AuthBasic>,
 ) -> Result<impl IntoResponse, ApiError> {
+    let player = verify_optional_header(auth)?;
     let txn = begin_txn(&state.conn).await?;
-    let n = entity::get_node(&txn, NodeID::from_xy(x, y)).await?;
+    let n = entity::get_visible_node(&txn, player, NodeID::from_xy(x, y)).await?;
     txn.commit().await?;
-    Ok(Attachment {
-        raw: MsgPak(n.data),
-        file_name: format!("{x}-{y}.msgpak"),
-    })
+    Ok((
+        [(TERRAIN_HEADER, n.terrain.to_value())],
+        Attachment {
+            raw: MsgPak(n.data),
+            file_name: format!("{x}-{y}.msgpak"),
+        },
+    ))
 }
 
 #[instrument(skip(state, auth), ret(level = Level::DEBUG), err(level = Level::INFO))]
@@ -269,6 +411,528 @@ pub async fn heat(
     Ok(Json(g))
 }
 
+#[instrument(skip(state, auth), ret(level = Level::DEBUG), err(level = Level::INFO))]
+pub async fn cool(
+    State(state): State<AppState>,
+    AuthBasic(auth): AuthBasic,
+    Path(gid): Path<i32>,
+    Json(cmd): Json<CoolCommand>,
+) -> Result<Json<Guest>, ApiError> {
+    // verify
+    let PlayerAuth { id, password } = verify_header(auth)?;
+
+    // transaction
+    let txn = begin_txn(&state.conn).await?;
+    let g = entity::cool(&txn, id, password, gid, cmd.at, cmd.energy).await?;
+    txn.commit().await?;
+
+    // return
+    Ok(Json(g))
+}
+
+#[instrument(skip(state, auth), ret(level = Level::DEBUG), err(level = Level::INFO))]
+pub async fn list_engine(
+    State(state): State<AppState>,
+    AuthBasic(auth): AuthBasic,
+) -> Result<Json<Vec<Engine>>, ApiError> {
+    let PlayerAuth { id, password } = verify_header(auth)?;
+    let txn = begin_txn(&state.conn).await?;
+    let es = entity::list_engine(&txn, id, password).await?;
+    txn.commit().await?;
+    Ok(Json(es))
+}
+
+#[instrument(skip(state, auth), ret(level = Level::DEBUG), err(level = Level::INFO))]
+pub async fn build_engine(
+    State(state): State<AppState>,
+    AuthBasic(auth): AuthBasic,
+    Path(gid): Path<i32>,
+    Json(cmd): Json<BuildEngineCommand>,
+) -> Result<Json<Engine>, ApiError> {
+    // verify
+    let PlayerAuth { id, password } = verify_header(auth)?;
+
+    // transaction
+    let txn = begin_txn(&state.conn).await?;
+    let e = entity::build_engine(&txn, id, password, gid, cmd.cell, cmd.other).await?;
+    txn.commit().await?;
+
+    // return
+    Ok(Json(e))
+}
+
+#[instrument(skip(state, auth), ret(level = Level::DEBUG), err(level = Level::INFO))]
+pub async fn collect_engine(
+    State(state): State<AppState>,
+    AuthBasic(auth): AuthBasic,
+    Path(gid): Path<i32>,
+    Json(cmd): Json<CollectEngineCommand>,
+) -> Result<Json<Guest>, ApiError> {
+    // verify
+    let PlayerAuth { id, password } = verify_header(auth)?;
+
+    // transaction
+    let txn = begin_txn(&state.conn).await?;
+    let g = entity::collect_engine(&txn, id, password, gid, cmd.engine).await?;
+    txn.commit().await?;
+
+    // return
+    Ok(Json(g))
+}
+
+#[instrument(skip(state, auth), ret(level = Level::DEBUG), err(level = Level::INFO))]
+pub async fn list_battery(
+    State(state): State<AppState>,
+    AuthBasic(auth): AuthBasic,
+    Path(gid): Path<i32>,
+) -> Result<Json<Vec<Battery>>, ApiError> {
+    let PlayerAuth { id, password } = verify_header(auth)?;
+    let txn = begin_txn(&state.conn).await?;
+    let bs = entity::list_battery(&txn, id, password, gid).await?;
+    txn.commit().await?;
+    Ok(Json(bs))
+}
+
+#[instrument(skip(state, auth), ret(level = Level::DEBUG), err(level = Level::INFO))]
+pub async fn build_battery(
+    State(state): State<AppState>,
+    AuthBasic(auth): AuthBasic,
+    Path(gid): Path<i32>,
+    Json(cmd): Json<BuildBatteryCommand>,
+) -> Result<Json<Battery>, ApiError> {
+    // verify
+    let PlayerAuth { id, password } = verify_header(auth)?;
+
+    // transaction
+    let txn = begin_txn(&state.conn).await?;
+    let b = entity::build_battery(&txn, id, password, gid, cmd.capacity, cmd.access).await?;
+    txn.commit().await?;
+
+    // return
+    Ok(Json(b))
+}
+
+#[instrument(skip(state, auth), ret(level = Level::DEBUG), err(level = Level::INFO))]
+pub async fn deposit_battery(
+    State(state): State<AppState>,
+    AuthBasic(auth): AuthBasic,
+    Path(gid): Path<i32>,
+    Json(cmd): Json<BatteryCommand>,
+) -> Result<Json<Battery>, ApiError> {
+    // verify
+    let PlayerAuth { id, password } = verify_header(auth)?;
+
+    // transaction
+    let txn = begin_txn(&state.conn).await?;
+    let b = entity::deposit_battery(&txn, id, password, gid, cmd.battery, cmd.energy).await?;
+    txn.commit().await?;
+
+    // return
+    Ok(Json(b))
+}
+
+#[instrument(skip(state, auth), ret(level = Level::DEBUG), err(level = Level::INFO))]
+pub async fn withdraw_battery(
+    State(state): State<AppState>,
+    AuthBasic(auth): AuthBasic,
+    Path(gid): Path<i32>,
+    Json(cmd): Json<BatteryCommand>,
+) -> Result<Json<Guest>, ApiError> {
+    // verify
+    let PlayerAuth { id, password } = verify_header(auth)?;
+
+    // transaction
+    let txn = begin_txn(&state.conn).await?;
+    let g = entity::withdraw_battery(&txn, id, password, gid, cmd.battery, cmd.energy).await?;
+    txn.commit().await?;
+
+    // return
+    Ok(Json(g))
+}
+
+#[instrument(skip(state, auth), ret(level = Level::DEBUG), err(level = Level::INFO))]
+pub async fn siphon(
+    State(state): State<AppState>,
+    AuthBasic(auth): AuthBasic,
+    Path(gid): Path<i32>,
+    Json(cmd): Json<SiphonCommand>,
+) -> Result<Json<Siphon>, ApiError> {
+    // verify
+    let PlayerAuth { id, password } = verify_header(auth)?;
+
+    // transaction
+    let txn = begin_txn(&state.conn).await?;
+    let s = entity::siphon(&txn, id, password, gid, cmd.target).await?;
+    txn.commit().await?;
+
+    // return
+    Ok(Json(s))
+}
+
+#[instrument(skip(state, auth), ret(level = Level::DEBUG), err(level = Level::INFO))]
+pub async fn list_siphon(
+    State(state): State<AppState>,
+    AuthBasic(auth): AuthBasic,
+) -> Result<Json<Vec<Siphon>>, ApiError> {
+    let PlayerAuth { id, password } = verify_header(auth)?;
+    let txn = begin_txn(&state.conn).await?;
+    let ss = entity::list_siphon(&txn, id, password).await?;
+    txn.commit().await?;
+    Ok(Json(ss))
+}
+
+#[instrument(skip(state, auth), ret(level = Level::DEBUG), err(level = Level::INFO))]
+pub async fn claim(
+    State(state): State<AppState>,
+    AuthBasic(auth): AuthBasic,
+    Path(gid): Path<i32>,
+    Json(cmd): Json<ClaimCommand>,
+) -> Result<Json<Claim>, ApiError> {
+    // verify
+    let PlayerAuth { id, password } = verify_header(auth)?;
+
+    // transaction
+    let txn = begin_txn(&state.conn).await?;
+    let c = entity::claim_node(&txn, id, password, gid, cmd.mode, cmd.duration, cmd.stake).await?;
+    txn.commit().await?;
+
+    // return
+    Ok(Json(c))
+}
+
+#[instrument(skip(state, auth), ret(level = Level::DEBUG), err(level = Level::INFO))]
+pub async fn collect_claim(
+    State(state): State<AppState>,
+    AuthBasic(auth): AuthBasic,
+    Path(gid): Path<i32>,
+) -> Result<Json<Guest>, ApiError> {
+    // verify
+    let PlayerAuth { id, password } = verify_header(auth)?;
+
+    // transaction
+    let txn = begin_txn(&state.conn).await?;
+    let g = entity::collect_claim(&txn, id, password, gid).await?;
+    txn.commit().await?;
+
+    // return
+    Ok(Json(g))
+}
+
+#[instrument(skip(state), err(level = Level::INFO))]
+pub async fn list_claim(
+    State(state): State<AppState>,
+    Path((x0, y0, x1, y1)): Path<(i16, i16, i16, i16)>,
+) -> Result<Json<Vec<Claim>>, ApiError> {
+    let region = Region::new(NodeID::from_xy(x0, y0), NodeID::from_xy(x1, y1));
+    Ok(Json(entity::list_claim(&state.conn, region).await?))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HeatmapQuery {
+    /// which temperature of the cells colours a node, the mean if omitted
+    #[serde(default)]
+    shade: Shade,
+    /// pixels along a side of a node, 4 if omitted
+    scale: Option<u16>,
+}
+
+#[instrument(skip(state, auth), err(level = Level::INFO))]
+pub async fn admin_heatmap(
+    State(state): State<AppState>,
+    Path((x0, y0, x1, y1)): Path<(i16, i16, i16, i16)>,
+    Query(q): Query<HeatmapQuery>,
+    auth: Option<AuthBearer>,
+) -> Result<impl IntoResponse, ApiError> {
+    verify_admin(&state, auth)?;
+    let region = Region::new(NodeID::from_xy(x0, y0), NodeID::from_xy(x1, y1));
+    let png = heatmap::render(&state.conn, region, q.shade, q.scale.unwrap_or(4)).await?;
+    Ok(([(CONTENT_TYPE, "image/png")], png))
+}
+
+#[instrument(skip(state), ret(level = Level::DEBUG), err(level = Level::INFO))]
+pub async fn get_alliance_public(
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+) -> Result<Json<PublicAlliance>, ApiError> {
+    Ok(Json(entity::get_alliance_public(&state.conn, id).await?))
+}
+
+#[instrument(skip(state, auth), ret(level = Level::DEBUG), err(level = Level::INFO))]
+pub async fn create_alliance(
+    State(state): State<AppState>,
+    AuthBasic(auth): AuthBasic,
+    Json(cmd): Json<CreateAllianceCommand>,
+) -> Result<Json<Alliance>, ApiError> {
+    // verify
+    let PlayerAuth { id, password } = verify_header(auth)?;
+
+    // transaction
+    let txn = begin_txn(&state.conn).await?;
+    let a = entity::create_alliance(&txn, id, password, cmd.name).await?;
+    txn.commit().await?;
+
+    // return
+    Ok(Json(a))
+}
+
+#[instrument(skip(state, auth), ret(level = Level::DEBUG), err(level = Level::INFO))]
+pub async fn invite_alliance(
+    State(state): State<AppState>,
+    AuthBasic(auth): AuthBasic,
+    Json(cmd): Json<AllianceMemberCommand>,
+) -> Result<Json<Invite>, ApiError> {
+    // verify
+    let PlayerAuth { id, password } = verify_header(auth)?;
+
+    // transaction
+    let txn = begin_txn(&state.conn).await?;
+    let i = entity::invite_alliance(&txn, id, password, cmd.player).await?;
+    txn.commit().await?;
+
+    // return
+    Ok(Json(i))
+}
+
+#[instrument(skip(state, auth), ret(level = Level::DEBUG), err(level = Level::INFO))]
+pub async fn list_invite(
+    State(state): State<AppState>,
+    AuthBasic(auth): AuthBasic,
+) -> Result<Json<Vec<Invite>>, ApiError> {
+    let PlayerAuth { id, password } = verify_header(auth)?;
+    let txn = begin_txn(&state.conn).await?;
+    let is = entity::list_invite(&txn, id, password).await?;
+    txn.commit().await?;
+    Ok(Json(is))
+}
+
+#[instrument(skip(state, auth), ret(level = Level::DEBUG), err(level = Level::INFO))]
+pub async fn accept_alliance(
+    State(state): State<AppState>,
+    AuthBasic(auth): AuthBasic,
+    Json(cmd): Json<AcceptAllianceCommand>,
+) -> Result<Json<Player>, ApiError> {
+    // verify
+    let PlayerAuth { id, password } = verify_header(auth)?;
+
+    // transaction
+    let txn = begin_txn(&state.conn).await?;
+    let p = entity::accept_alliance(&txn, id, password, cmd.alliance).await?;
+    txn.commit().await?;
+
+    // return
+    Ok(Json(p))
+}
+
+#[instrument(skip(state, auth), ret(level = Level::DEBUG), err(level = Level::INFO))]
+pub async fn leave_alliance(
+    State(state): State<AppState>,
+    AuthBasic(auth): AuthBasic,
+) -> Result<Json<Player>, ApiError> {
+    // verify
+    let PlayerAuth { id, password } = verify_header(auth)?;
+
+    // transaction
+    let txn = begin_txn(&state.conn).await?;
+    let p = entity::leave_alliance(&txn, id, password).await?;
+    txn.commit().await?;
+
+    // return
+    Ok(Json(p))
+}
+
+#[instrument(skip(state, auth), ret(level = Level::DEBUG), err(level = Level::INFO))]
+pub async fn kick_alliance(
+    State(state): State<AppState>,
+    AuthBasic(auth): AuthBasic,
+    Json(cmd): Json<AllianceMemberCommand>,
+) -> Result<Json<PublicPlayer>, ApiError> {
+    // verify
+    let PlayerAuth { id, password } = verify_header(auth)?;
+
+    // transaction
+    let txn = begin_txn(&state.conn).await?;
+    let p = entity::kick_alliance(&txn, id, password, cmd.player).await?;
+    txn.commit().await?;
+
+    // return
+    Ok(Json(p))
+}
+
+#[instrument(skip(state, auth), ret(level = Level::DEBUG), err(level = Level::INFO))]
+pub async fn list_ally_guest(
+    State(state): State<AppState>,
+    AuthBasic(auth): AuthBasic,
+) -> Result<Json<Vec<DetectedGuest>>, ApiError> {
+    let PlayerAuth { id, password } = verify_header(auth)?;
+    let txn = begin_txn(&state.conn).await?;
+    let gs = entity::list_ally_guest(&txn, id, password).await?;
+    txn.commit().await?;
+    Ok(Json(gs))
+}
+
+#[instrument(skip(state, auth), ret(level = Level::DEBUG), err(level = Level::INFO))]
+pub async fn give(
+    State(state): State<AppState>,
+    AuthBasic(auth): AuthBasic,
+    Path(gid): Path<i32>,
+    Json(cmd): Json<GiveCommand>,
+) -> Result<Json<Guest>, ApiError> {
+    // verify
+    let PlayerAuth { id, password } = verify_header(auth)?;
+
+    // transaction
+    let txn = begin_txn(&state.conn).await?;
+    let g = entity::give_energy(&txn, id, password, gid, cmd.target, cmd.energy).await?;
+    txn.commit().await?;
+
+    // return
+    Ok(Json(g))
+}
+
+#[instrument(skip(state, auth), ret(level = Level::DEBUG), err(level = Level::INFO))]
+pub async fn post_message(
+    State(state): State<AppState>,
+    AuthBasic(auth): AuthBasic,
+    Path(gid): Path<i32>,
+    Json(cmd): Json<PostMessageCommand>,
+) -> Result<Json<Message>, ApiError> {
+    // verify
+    let PlayerAuth { id, password } = verify_header(auth)?;
+
+    // transaction
+    let txn = begin_txn(&state.conn).await?;
+    let m = entity::post_message(&txn, id, password, gid, cmd.content, cmd.radius).await?;
+    txn.commit().await?;
+
+    // return
+    Ok(Json(m))
+}
+
+#[instrument(skip(state, auth), ret(level = Level::DEBUG), err(level = Level::INFO))]
+pub async fn list_message(
+    State(state): State<AppState>,
+    AuthBasic(auth): AuthBasic,
+    Path(gid): Path<i32>,
+    Query(query): Query<ListMessageQuery>,
+) -> Result<Json<Vec<Message>>, ApiError> {
+    let PlayerAuth { id, password } = verify_header(auth)?;
+    let txn = begin_txn(&state.conn).await?;
+    let ms = entity::list_message(&txn, id, password, gid, query.after).await?;
+    txn.commit().await?;
+    Ok(Json(ms))
+}
+
+#[instrument(skip(state, auth), ret(level = Level::DEBUG), err(level = Level::INFO))]
+pub async fn post_offer(
+    State(state): State<AppState>,
+    AuthBasic(auth): AuthBasic,
+    Path(gid): Path<i32>,
+    Json(cmd): Json<PostOfferCommand>,
+) -> Result<Json<Offer>, ApiError> {
+    // verify
+    let PlayerAuth { id, password } = verify_header(auth)?;
+
+    // transaction
+    let txn = begin_txn(&state.conn).await?;
+    let o = entity::post_offer(&txn, id, password, gid, cmd.goods, cmd.price, cmd.expire).await?;
+    txn.commit().await?;
+
+    // return
+    Ok(Json(o))
+}
+
+#[instrument(skip(state, auth), ret(level = Level::DEBUG), err(level = Level::INFO))]
+pub async fn accept_offer(
+    State(state): State<AppState>,
+    AuthBasic(auth): AuthBasic,
+    Path(gid): Path<i32>,
+    Json(cmd): Json<OfferCommand>,
+) -> Result<Json<Offer>, ApiError> {
+    // verify
+    let PlayerAuth { id, password } = verify_header(auth)?;
+
+    // transaction
+    let txn = begin_txn(&state.conn).await?;
+    let o = entity::accept_offer(&txn, id, password, gid, cmd.offer).await?;
+    txn.commit().await?;
+
+    // return
+    Ok(Json(o))
+}
+
+#[instrument(skip(state, auth), ret(level = Level::DEBUG), err(level = Level::INFO))]
+pub async fn cancel_offer(
+    State(state): State<AppState>,
+    AuthBasic(auth): AuthBasic,
+    Json(cmd): Json<OfferCommand>,
+) -> Result<Json<Offer>, ApiError> {
+    // verify
+    let PlayerAuth { id, password } = verify_header(auth)?;
+
+    // transaction
+    let txn = begin_txn(&state.conn).await?;
+    let o = entity::cancel_offer(&txn, id, password, cmd.offer).await?;
+    txn.commit().await?;
+
+    // return
+    Ok(Json(o))
+}
+
+#[instrument(skip(state, auth), ret(level = Level::DEBUG), err(level = Level::INFO))]
+pub async fn list_own_offer(
+    State(state): State<AppState>,
+    AuthBasic(auth): AuthBasic,
+) -> Result<Json<Vec<Offer>>, ApiError> {
+    let PlayerAuth { id, password } = verify_header(auth)?;
+    let txn = begin_txn(&state.conn).await?;
+    let os = entity::list_own_offer(&txn, id, password).await?;
+    txn.commit().await?;
+    Ok(Json(os))
+}
+
+#[instrument(skip(state), ret(level = Level::DEBUG), err(level = Level::INFO))]
+pub async fn list_offer(
+    State(state): State<AppState>,
+    Query(query): Query<ListOfferQuery>,
+) -> Result<Json<Vec<Offer>>, ApiError> {
+    let txn = begin_txn(&state.conn).await?;
+    let os = entity::list_offer(&txn, query.kind).await?;
+    txn.commit().await?;
+    Ok(Json(os))
+}
+
+#[instrument(skip(state), ret(level = Level::DEBUG), err(level = Level::INFO))]
+pub async fn leaderboard(
+    State(state): State<AppState>,
+    Query(query): Query<LeaderboardQuery>,
+) -> Result<Json<Vec<Ranked>>, ApiError> {
+    Ok(Json(
+        entity::list_leaderboard(&state.conn, query.by, query.page, query.size).await?,
+    ))
+}
+
+#[instrument(skip(state), ret(level = Level::DEBUG), err(level = Level::INFO))]
+pub async fn list_event(State(state): State<AppState>) -> Result<Json<Vec<WorldEvent>>, ApiError> {
+    Ok(Json(entity::list_event(&state.conn).await?))
+}
+
+#[instrument(skip(state), ret(level = Level::DEBUG), err(level = Level::INFO))]
+pub async fn get_season(State(state): State<AppState>) -> Result<Json<Season>, ApiError> {
+    Ok(Json(entity::get_season(&state.conn).await?))
+}
+
+#[instrument(skip(state, auth), ret(level = Level::DEBUG), err(level = Level::INFO))]
+pub async fn list_visit(
+    State(state): State<AppState>,
+    AuthBasic(auth): AuthBasic,
+) -> Result<Json<Vec<Visit>>, ApiError> {
+    let PlayerAuth { id, password } = verify_header(auth)?;
+    let txn = begin_txn(&state.conn).await?;
+    let vs = entity::list_visit(&txn, id, password).await?;
+    txn.commit().await?;
+    Ok(Json(vs))
+}
+
 fn verify_header(auth: (String, Option<String>)) -> Result<PlayerAuth, ApiError> {
     let (id, password) = auth;
     let password = password.ok_or(ApiError::AuthHeader)?;
@@ -279,6 +943,27 @@ fn verify_header(auth: (String, Option<String>)) -> Result<PlayerAuth, ApiError>
     }
 }
 
+/// Credentials if given, for endpoints open to anyone.
+fn verify_optional_header(auth: Option<AuthBasic>) -> Result<Option<(i32, String)>, ApiError> {
+    auth.map(|AuthBasic(auth)| {
+        verify_header(auth).map(|PlayerAuth { id, password }| (id, password))
+    })
+    .transpose()
+}
+
+/// Admin endpoints are open only with the configured token.
+fn verify_admin(state: &AppState, auth: Option<AuthBearer>) -> Result<(), ApiError> {
+    match (&state.admin_token, auth) {
+        // compared in constant time, so that the time taken tells nothing about the token
+        (Some(token), Some(AuthBearer(given)))
+            if bool::from(token.as_bytes().ct_eq(given.as_bytes())) =>
+        {
+            Ok(())
+        }
+        _ => Err(ApiError::AdminDenied),
+    }
+}
+
 async fn begin_txn(db: &DatabaseConnection) -> Result<DatabaseTransaction, DbErr> {
     db.begin_with_config(
         Some(IsolationLevel::RepeatableRead), // set isolate level
@@ -16,11 +16,17 @@ pub mod handler;
 
 #[instrument(skip(db))]
 pub async fn http_daemon<C: AsRef<DbConn>>(
-    config::Http { address, port, .. }: config::Http,
+    config::Http {
+        address,
+        port,
+        admin_token,
+        ..
+    }: config::Http,
     db: C,
 ) -> Result<(), RuntimeError> {
     let state = AppState {
         conn: db.as_ref().clone(),
+        admin_token,
     };
 
     let router = Router::new()
@@ -30,15 +36,59 @@ pub async fn http_daemon<C: AsRef<DbConn>>(
         .route("/player/verify", get(handler::verify_player))
         .route("/player/guest", get(handler::list_guest))
         .route("/player/guest/spawn", get(handler::spawn_guest))
+        .route("/player/engine", get(handler::list_engine))
+        .route("/player/siphon", get(handler::list_siphon))
+        .route("/player/alliance/create", post(handler::create_alliance))
+        .route(
+            "/player/alliance/invite",
+            get(handler::list_invite).post(handler::invite_alliance),
+        )
+        .route("/player/alliance/accept", post(handler::accept_alliance))
+        .route("/player/alliance/leave", post(handler::leave_alliance))
+        .route("/player/alliance/kick", post(handler::kick_alliance))
+        .route("/player/alliance/guest", get(handler::list_ally_guest))
+        .route("/player/offer", get(handler::list_own_offer))
+        .route("/player/visit", get(handler::list_visit))
+        .route("/player/offer/cancel", post(handler::cancel_offer))
+        .route("/alliance/:id", get(handler::get_alliance_public))
+        .route("/offer", get(handler::list_offer))
+        .route("/leaderboard", get(handler::leaderboard))
+        .route("/season", get(handler::get_season))
+        .route("/event", get(handler::list_event))
         .route("/node/:x/:y", get(handler::get_node))
         .route("/node/bytes/:x/:y", get(handler::get_node_bytes))
         .route("/node/msgpak/:x/:y", get(handler::get_node_msgpak))
+        .route("/claim/:x0/:y0/:x1/:y1", get(handler::list_claim))
         .route("/guest/:id", get(handler::get_guest))
         .route("/guest/walk/:id", post(handler::walk))
         .route("/guest/harvest/:id", post(handler::harvest))
         .route("/guest/arrange/:id", post(handler::arrange))
         .route("/guest/detect/:id", get(handler::detect))
+        .route("/guest/siphon/:id", post(handler::siphon))
+        .route("/guest/give/:id", post(handler::give))
+        .route("/guest/offer/:id", post(handler::post_offer))
+        .route("/guest/offer/accept/:id", post(handler::accept_offer))
+        .route(
+            "/guest/message/:id",
+            get(handler::list_message).post(handler::post_message),
+        )
+        .route("/guest/claim/:id", post(handler::claim))
+        .route("/guest/claim/collect/:id", post(handler::collect_claim))
         .route("/guest/heat/:id", post(handler::heat))
+        .route("/guest/cool/:id", post(handler::cool))
+        .route("/guest/engine/build/:id", post(handler::build_engine))
+        .route("/guest/engine/collect/:id", post(handler::collect_engine))
+        .route("/guest/battery/:id", get(handler::list_battery))
+        .route("/guest/battery/build/:id", post(handler::build_battery))
+        .route("/guest/battery/deposit/:id", post(handler::deposit_battery))
+        .route(
+            "/guest/battery/withdraw/:id",
+            post(handler::withdraw_battery),
+        )
+        .route(
+            "/admin/heatmap/:x0/:y0/:x1/:y1",
+            get(handler::admin_heatmap),
+        )
         .layer(CompressionLayer::new())
         .with_state(state);
 
@@ -64,4 +114,5 @@ impl IntoResponse for ApiError {
 #[derive(Debug, Clone)]
 pub struct AppState {
     pub conn: DbConn,
+    pub admin_token: Option<String>,
 }
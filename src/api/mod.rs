@@ -12,6 +12,7 @@ use crate::err::{ApiError, ModelError, OperationError};
 pub mod http;
 // pub mod zmq;
 
+#[allow(dead_code)]
 pub struct NoDownload<T>(T);
 impl<T: IntoResponse> IntoResponse for NoDownload<T> {
     fn into_response(self) -> axum::response::Response {
@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, sync::OnceLock};
 
 use serde::{Deserialize, Serialize};
 use tokio::{fs::File, io::AsyncReadExt};
@@ -10,6 +10,14 @@ pub struct Root {
     pub db: Db,
     pub http: Http,
     // pub socket: Socket,
+    #[serde(default)]
+    pub rule: Rule,
+    #[serde(default)]
+    pub leaderboard: Leaderboard,
+    #[serde(default)]
+    pub season: Season,
+    #[serde(default)]
+    pub event: Event,
 }
 
 #[derive(Debug, Deserialize)]
@@ -38,8 +46,12 @@ pub struct Http {
     pub enable: bool,
     pub address: String,
     pub port: u16,
+    /// bearer token of the admin endpoints, which are disabled if unset
+    #[serde(default)]
+    pub admin_token: Option<String>,
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Socket {
     pub enable: bool,
@@ -47,11 +59,640 @@ pub struct Socket {
     pub port: u16,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Leaderboard {
+    /// seconds between two refreshes of the leaderboard
+    pub interval: u64,
+}
+impl Default for Leaderboard {
+    fn default() -> Self {
+        Self { interval: 300 }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Season {
+    /// seconds a season lasts, only informative, seasons are ended by the `season` command
+    pub length: i64,
+    /// where the world is archived at the end of a season
+    pub archive_dir: PathBuf,
+}
+impl Default for Season {
+    fn default() -> Self {
+        Self {
+            length: 2592000,
+            archive_dir: PathBuf::from("data/season"),
+        }
+    }
+}
+
+/// World events, announced in advance and applied by a scheduler.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Event {
+    pub enable: bool,
+    /// seconds between two runs of the scheduler
+    pub interval: u64,
+    /// seconds an event is announced before it happens
+    pub notice: i64,
+    /// nodes updated in one transaction
+    pub batch: u64,
+    /// solar flare heats all the cells within this distance of a node
+    pub flare_radius: i16,
+    /// degrees a solar flare heats every cell
+    pub flare_heat: i16,
+    /// rows of nodes a cold front covers
+    pub front_width: i16,
+    /// columns of nodes a cold front covers
+    pub front_length: i16,
+    /// degrees a cold front cools every cell
+    pub front_cool: i16,
+    /// energy each guest on the node gains from a bonus
+    pub bonus_energy: i64,
+}
+impl Default for Event {
+    fn default() -> Self {
+        Self {
+            enable: true,
+            interval: 600,
+            notice: 1800,
+            batch: 256,
+            flare_radius: 4,
+            flare_heat: 16,
+            front_width: 3,
+            front_length: 32,
+            front_cool: 16,
+            bonus_energy: 500,
+        }
+    }
+}
+
+/// Game rules, shared by all the api daemons.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Rule {
+    pub walk: Walk,
+    pub thermal: Thermal,
+    pub cool: Cool,
+    pub engine: Engine,
+    pub battery: Battery,
+    pub siphon: Siphon,
+    pub claim: Claim,
+    pub alliance: Alliance,
+    pub chat: Chat,
+    pub market: Market,
+    pub fog: Fog,
+    pub achievement: Achievement,
+    pub world: World,
+    pub terrain: Terrain,
+    pub regen: Regen,
+    pub spawn: Spawn,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Walk {
+    /// energy cost of moving up, down, left or right
+    pub straight_cost: i64,
+    /// energy cost of moving to one of the four corners
+    pub diagonal_cost: i64,
+}
+impl Default for Walk {
+    fn default() -> Self {
+        Self {
+            straight_cost: 1,
+            diagonal_cost: 2,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Terrain {
+    /// extra energy to walk into an insulator node
+    pub insulator_walk_cost: i64,
+    /// guest temperature settles this many times slower on an insulator node
+    pub insulator_thermal_factor: f32,
+    /// guest temperature settles this many times faster on a conductor node
+    pub conductor_thermal_factor: f32,
+    /// extra energy to walk into a vent node
+    pub vent_walk_cost: i64,
+    /// part of the heat harvested from a vent cell drawn back from the other cells at once, within 0 to 1
+    pub vent_recharge: f32,
+}
+impl Default for Terrain {
+    fn default() -> Self {
+        Self {
+            insulator_walk_cost: 2,
+            insulator_thermal_factor: 4.0,
+            conductor_thermal_factor: 4.0,
+            vent_walk_cost: 1,
+            vent_recharge: 0.5,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Spawn {
+    /// where the first guest of a player spawns
+    pub strategy: SpawnStrategy,
+    /// nodes away from the origin, moving diagonally counts as one, the spawn looks within
+    pub radius: i16,
+    /// random nodes tried when the nodes of the strategy are all exhausted
+    pub attempts: u32,
+    /// energy a new guest must be able to harvest from the spawn node, or it is exhausted
+    pub min_energy: i64,
+}
+impl Default for Spawn {
+    fn default() -> Self {
+        Self {
+            strategy: SpawnStrategy::default(),
+            radius: 8,
+            attempts: 16,
+            min_energy: 256,
+        }
+    }
+}
+
+/// Where the first guest of a player spawns.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpawnStrategy {
+    /// the origin
+    Origin,
+    /// a random node within the radius
+    Random,
+    /// the node within the radius with the fewest guests on
+    LeastPopulated,
+    /// a random spawn point designated by a map, the origin if there is none
+    #[default]
+    Points,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Regen {
+    /// seconds for the difference between a cell and its generated value to halve, 0 to disable
+    pub half_life: f32,
+}
+impl Default for Regen {
+    fn default() -> Self {
+        Self { half_life: 86400.0 }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Thermal {
+    /// seconds for the temperature difference between a guest and its node to halve
+    pub half_life: f32,
+}
+impl Default for Thermal {
+    fn default() -> Self {
+        Self { half_life: 600.0 }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Cool {
+    /// upper bound of the heat removed per energy spent
+    pub max_cop: f32,
+}
+impl Default for Cool {
+    fn default() -> Self {
+        Self { max_cop: 8.0 }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Engine {
+    /// energy cost of building an engine
+    pub build_cost: i64,
+    /// seconds of one carnot cycle
+    pub tick: i64,
+    /// max cycles run at once, cycles beyond are wasted
+    pub max_cycles: i64,
+}
+impl Default for Engine {
+    fn default() -> Self {
+        Self {
+            build_cost: 64,
+            tick: 60,
+            max_cycles: 1440,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Battery {
+    /// capacity bought by one energy when building
+    pub capacity_per_energy: i64,
+    pub max_capacity: i64,
+    /// seconds for the stored energy to halve
+    pub half_life: f64,
+}
+impl Default for Battery {
+    fn default() -> Self {
+        Self {
+            capacity_per_energy: 4,
+            max_capacity: 65536,
+            half_life: 604800.0,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Siphon {
+    /// energy cost of an attempt, paid even if failed
+    pub cost: i64,
+    /// max part of the victim's spare energy drained at once
+    pub max_ratio: f64,
+    /// energy of the victim that can never be drained
+    pub reserve: i64,
+    /// seconds a victim is protected after drained
+    pub protection: i64,
+}
+impl Default for Siphon {
+    fn default() -> Self {
+        Self {
+            cost: 4,
+            max_ratio: 0.5,
+            reserve: 16,
+            protection: 300,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Claim {
+    /// energy cost of claiming a node for an hour
+    pub cost_per_hour: i64,
+    /// max seconds claimed at once
+    pub max_duration: i64,
+    /// part of energy paid to the claimant, when others use a taxed node
+    pub tax_rate: f64,
+    /// times of the remaining stake a contester must pay
+    pub contest_factor: f64,
+}
+impl Default for Claim {
+    fn default() -> Self {
+        Self {
+            cost_per_hour: 8,
+            max_duration: 259200,
+            tax_rate: 0.2,
+            contest_factor: 1.5,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Alliance {
+    pub max_members: u64,
+    /// whether members can give energy to each other's guests
+    pub give: bool,
+}
+impl Default for Alliance {
+    fn default() -> Self {
+        Self {
+            max_members: 16,
+            give: true,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Chat {
+    /// max characters of a message
+    pub max_length: usize,
+    /// max nodes away a message is broadcast
+    pub max_radius: i16,
+    /// energy cost per node of the broadcast radius
+    pub broadcast_cost: i64,
+    /// seconds a message is kept
+    pub retention: i64,
+    /// messages kept on a node, older ones are dropped
+    pub max_per_node: u64,
+}
+impl Default for Chat {
+    fn default() -> Self {
+        Self {
+            max_length: 256,
+            max_radius: 8,
+            broadcast_cost: 2,
+            retention: 86400,
+            max_per_node: 100,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Market {
+    /// max seconds an offer stays open
+    pub max_expire: i64,
+    /// max open offers of a player
+    pub max_open: u64,
+    /// max seconds of the node access bought at once
+    pub max_access_duration: i64,
+}
+impl Default for Market {
+    fn default() -> Self {
+        Self {
+            max_expire: 604800,
+            max_open: 16,
+            max_access_duration: 259200,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Fog {
+    /// serve full node data only to players who have seen the node
+    pub enable: bool,
+    /// nodes away a guest sees around itself
+    pub sight: i16,
+}
+impl Default for Fog {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            sight: 1,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Achievement {
+    /// nodes away from the origin to unlock the explorer
+    pub distance: i32,
+    /// guests owned to unlock the colony
+    pub colony: u64,
+}
+impl Default for Achievement {
+    fn default() -> Self {
+        Self {
+            distance: 32,
+            colony: 10,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct World {
+    /// node data is generated from the seed and the node id,
+    /// servers sharing the seed generate the same world
+    pub seed: u64,
+    pub generator: Generator,
+    pub climate: Climate,
+    pub flat: Flat,
+    /// map file of the `map` generator
+    pub map: PathBuf,
+    pub bounds: Bounds,
+}
+
+/// Nodes of the world, a rectangle with bounds included, which should cover the origin.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Bounds {
+    pub x0: i16,
+    pub y0: i16,
+    pub x1: i16,
+    pub y1: i16,
+    pub edge: Edge,
+}
+impl Default for Bounds {
+    fn default() -> Self {
+        Self {
+            x0: i16::MIN,
+            y0: i16::MIN,
+            x1: i16::MAX,
+            y1: i16::MAX,
+            edge: Edge::default(),
+        }
+    }
+}
+
+/// What is beyond the bounds of the world.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Edge {
+    /// nothing, nodes beyond are rejected
+    #[default]
+    Wall,
+    /// the other side, the world is a torus
+    Wrap,
+}
+
+/// How node data is generated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Generator {
+    /// every node independent, uniform random cells of uniform random length
+    #[default]
+    Uniform,
+    /// like `uniform` but ignoring the seed, never the same twice
+    Random,
+    /// temperature fields coherent over nodes, from layered noise
+    Climate,
+    /// nodes designed in the map file, the rest flat as the map fills
+    Map,
+    /// every node the same, for tests
+    Flat,
+}
+
+/// Nodes all alike, every cell at the same temperature.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Flat {
+    pub length: usize,
+    pub temperature: i8,
+}
+impl Default for Flat {
+    fn default() -> Self {
+        Self {
+            length: 1024,
+            temperature: 0,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Climate {
+    /// nodes across the largest climate features
+    pub scale: f32,
+    /// layers of noise, each half the size of the previous one
+    pub octaves: u32,
+    /// weight of a layer relative to the previous one
+    pub persistence: f32,
+    /// mean temperature of a node at the extreme of the climate noise
+    pub amplitude: f32,
+    /// cells deviate from the mean temperature of their node by up to this
+    pub spread: i16,
+    /// nodes across the hotspot noise
+    pub hotspot_scale: f32,
+    /// hotspot noise above this makes a hotspot, within 0 to 1, higher is rarer
+    pub hotspot_threshold: f32,
+    /// mean temperature of a hotspot
+    pub hotspot_temperature: i16,
+    /// nodes across the terrain noise
+    pub terrain_scale: f32,
+    /// terrain noise above this makes a wall, within 0 to 1, higher is rarer
+    pub wall_threshold: f32,
+    /// terrain noise below the negative of this makes a conductor, within 0 to 1, higher is rarer
+    pub conductor_threshold: f32,
+    /// most cells of a node in each biome
+    pub length: BiomeLength,
+}
+impl Default for Climate {
+    fn default() -> Self {
+        Self {
+            scale: 64.0,
+            octaves: 4,
+            persistence: 0.5,
+            amplitude: 160.0,
+            spread: 24,
+            hotspot_scale: 8.0,
+            hotspot_threshold: 0.9,
+            hotspot_temperature: 110,
+            terrain_scale: 12.0,
+            wall_threshold: 0.7,
+            conductor_threshold: 0.7,
+            length: BiomeLength::default(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BiomeLength {
+    pub frozen: usize,
+    pub cold: usize,
+    pub temperate: usize,
+    pub hot: usize,
+    pub scorched: usize,
+    pub hotspot: usize,
+}
+impl Default for BiomeLength {
+    fn default() -> Self {
+        Self {
+            frozen: 1024,
+            cold: 4096,
+            temperate: 16384,
+            hot: 4096,
+            scorched: 1024,
+            hotspot: 256,
+        }
+    }
+}
+
+static RULE: OnceLock<Rule> = OnceLock::new();
+
+/// Install the game rules after validating them, only the first call takes effect.
+pub fn init_rule(rule: Rule) -> Result<(), RuntimeError> {
+    rule.validate()?;
+    let _ = RULE.set(rule);
+    Ok(())
+}
+
+impl Rule {
+    /// Reject rules that would break the game, such as a walk granting energy
+    /// or a world without the origin.
+    pub fn validate(&self) -> Result<(), RuntimeError> {
+        let invalid = |desc: String| Err(RuntimeError::InvalidRule(desc));
+        for (name, cost) in [
+            ("walk.straight_cost", self.walk.straight_cost),
+            ("walk.diagonal_cost", self.walk.diagonal_cost),
+            (
+                "terrain.insulator_walk_cost",
+                self.terrain.insulator_walk_cost,
+            ),
+            ("terrain.vent_walk_cost", self.terrain.vent_walk_cost),
+        ] {
+            if cost < 0 {
+                return invalid(format!("{name} is {cost}, must not be negative"));
+            }
+        }
+        for (name, value) in [
+            ("spawn.radius", self.spawn.radius as i64),
+            ("chat.retention", self.chat.retention),
+        ] {
+            if value < 0 {
+                return invalid(format!("{name} is {value}, must not be negative"));
+            }
+        }
+        let rate = self.claim.tax_rate;
+        if !(0.0..=1.0).contains(&rate) {
+            return invalid(format!("claim.tax_rate is {rate}, must be within 0 to 1"));
+        }
+        for (name, value) in [
+            ("battery.half_life", self.battery.half_life),
+            ("thermal.half_life", self.thermal.half_life as f64),
+            ("cool.max_cop", self.cool.max_cop as f64),
+        ] {
+            if value.is_nan() || value <= 0.0 {
+                return invalid(format!("{name} is {value}, must be positive"));
+            }
+        }
+        // 0 disables regen
+        let regen = self.regen.half_life;
+        if regen.is_nan() || regen < 0.0 {
+            return invalid(format!("regen.half_life is {regen}, must not be negative"));
+        }
+        let b = &self.world.bounds;
+        if b.x1 < b.x0 || b.y1 < b.y0 {
+            return invalid(format!(
+                "world.bounds ({}, {}) to ({}, {}) is empty",
+                b.x0, b.y0, b.x1, b.y1
+            ));
+        }
+        if !(b.x0..=b.x1).contains(&0) || !(b.y0..=b.y1).contains(&0) {
+            return invalid(format!(
+                "world.bounds ({}, {}) to ({}, {}) must cover the origin",
+                b.x0, b.y0, b.x1, b.y1
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Game rules installed by [`init_rule`], or the default rules.
+///
+/// Rules are global since they are also required inside model hooks,
+/// where no state could be passed through.
+pub fn rule() -> &'static Rule {
+    RULE.get_or_init(Rule::default)
+}
+
 pub async fn read_from_file(path: PathBuf) -> Result<Root, RuntimeError> {
     let mut file = File::open(path).await?;
     let mut contents = String::new();
     file.read_to_string(&mut contents).await?;
 
     let config: Root = toml::from_str(&contents)?;
+    check_season_length(config.season.length)?;
     Ok(config)
 }
+
+/// Reject a negative season length, from the config or the `season` command.
+pub fn check_season_length(length: i64) -> Result<(), RuntimeError> {
+    if length < 0 {
+        return Err(RuntimeError::InvalidRule(format!(
+            "season.length is {length}, must not be negative"
+        )));
+    }
+    Ok(())
+}
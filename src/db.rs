@@ -74,14 +74,14 @@ pub async fn prepare_db(config: config::Db) -> Result<DatabaseInstance, RuntimeE
     entity::node::Model::prepare_origin(&conn).await?;
 
     if let Some(embed_db) = embed_db {
-        Ok(DatabaseInstance::Embed(embed_db, conn))
+        Ok(DatabaseInstance::Embed(Box::new(embed_db), conn))
     } else {
         Ok(DatabaseInstance::Remote(conn))
     }
 }
 
 pub enum DatabaseInstance {
-    Embed(PgEmbed, DbConn),
+    Embed(Box<PgEmbed>, DbConn),
     Remote(DbConn),
 }
 impl AsRef<DbConn> for DatabaseInstance {
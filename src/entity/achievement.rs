@@ -0,0 +1,134 @@
+use chrono::Utc;
+use sea_orm::{entity::prelude::*, sea_query::OnConflict, Iterable, QueryOrder, Set};
+use serde::{Deserialize, Serialize};
+
+use crate::{config, world};
+use entropy_base::grid::NodeID;
+
+/// An achievement unlocked by a player.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "achievement")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub player_id: i32,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub kind: Kind,
+    pub unlocked_at: DateTimeUtc,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(32))")]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    /// harvest any energy
+    #[sea_orm(string_value = "first_harvest")]
+    FirstHarvest,
+    /// walk `rule.achievement.distance` nodes away from the origin
+    #[sea_orm(string_value = "explorer")]
+    Explorer,
+    /// own `rule.achievement.colony` guests
+    #[sea_orm(string_value = "colony")]
+    Colony,
+    /// leave a cell at 0K by harvesting or cooling
+    #[sea_orm(string_value = "absolute_zero")]
+    AbsoluteZero,
+    /// siphon a guest successfully
+    #[sea_orm(string_value = "siphoner")]
+    Siphoner,
+    /// claim a node
+    #[sea_orm(string_value = "landlord")]
+    Landlord,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::player::Entity",
+        from = "Column::PlayerId",
+        to = "super::player::Column::Id"
+    )]
+    Player,
+}
+
+impl Related<super::player::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Player.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// What a guest action ended up with, checked against the achievements.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// energy harvested and the cell left
+    Harvest {
+        energy: i64,
+        cell: i8,
+    },
+    /// node walked to
+    Walk(NodeID),
+    /// guests owned after arranging or buying a guest
+    Arrange {
+        guests: u64,
+    },
+    /// the cell left after cooling
+    Cool {
+        cell: i8,
+    },
+    Siphon {
+        success: bool,
+    },
+    Claim,
+}
+
+impl Kind {
+    pub fn reached(self, event: Event) -> bool {
+        let rule = &config::rule().achievement;
+        match (self, event) {
+            (Kind::FirstHarvest, Event::Harvest { energy, .. }) => energy > 0,
+            (Kind::Explorer, Event::Walk(at)) => {
+                world::distance(at, NodeID::ORIGIN) >= rule.distance
+            }
+            (Kind::Colony, Event::Arrange { guests }) => guests >= rule.colony,
+            (Kind::AbsoluteZero, Event::Harvest { cell, .. } | Event::Cool { cell }) => {
+                cell == i8::MIN
+            }
+            (Kind::Siphoner, Event::Siphon { success }) => success,
+            (Kind::Landlord, Event::Claim) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Unlock all the achievements reached by the event, those unlocked before are kept.
+pub async fn evaluate<C: ConnectionTrait>(
+    db: &C,
+    player_id: i32,
+    event: Event,
+) -> Result<(), DbErr> {
+    let now = Utc::now();
+    for kind in Kind::iter().filter(|k| k.reached(event)) {
+        Entity::insert(ActiveModel {
+            player_id: Set(player_id),
+            kind: Set(kind),
+            unlocked_at: Set(now),
+        })
+        .on_conflict(
+            OnConflict::columns([Column::PlayerId, Column::Kind])
+                .do_nothing()
+                .to_owned(),
+        )
+        .exec_without_returning(db)
+        .await?;
+    }
+    Ok(())
+}
+
+pub async fn list<C: ConnectionTrait>(db: &C, player_id: i32) -> Result<Vec<Model>, DbErr> {
+    Entity::find()
+        .filter(Column::PlayerId.eq(player_id))
+        .order_by_asc(Column::UnlockedAt)
+        .all(db)
+        .await
+}
@@ -0,0 +1,189 @@
+use chrono::Utc;
+use sea_orm::{entity::prelude::*, IntoActiveModel, QueryOrder, QuerySelect, Set};
+use serde::{Deserialize, Serialize};
+
+use crate::{config, err::OperationError};
+
+use super::{invite, player, variant::PublicPlayer};
+
+/// A group of players, members share guest positions and never harm each other.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "alliance")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub name: String,
+    /// the member able to invite and kick
+    pub leader_id: i32,
+    pub created_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(has_many = "super::player::Entity")]
+    Player,
+    #[sea_orm(has_many = "super::invite::Entity")]
+    Invite,
+}
+
+impl Related<super::player::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Player.def()
+    }
+}
+
+impl Related<super::invite::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Invite.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// Whether two different players are in the same alliance.
+pub async fn allied<C: ConnectionTrait>(db: &C, one: i32, other: i32) -> Result<bool, DbErr> {
+    if one == other {
+        return Ok(false);
+    }
+    let ids: Vec<Option<i32>> = player::Entity::find()
+        .select_only()
+        .column(player::Column::AllianceId)
+        .filter(player::Column::Id.is_in([one, other]))
+        .into_tuple()
+        .all(db)
+        .await?;
+    Ok(matches!(ids[..], [Some(a), Some(b)] if a == b))
+}
+
+impl Model {
+    /// Found an alliance led by the player.
+    pub async fn create<C: ConnectionTrait>(
+        db: &C,
+        leader: player::Model,
+        name: String,
+    ) -> Result<Model, OperationError> {
+        if leader.alliance_id.is_some() {
+            return Err(OperationError::AlreadyInAlliance);
+        }
+        let a = ActiveModel {
+            name: Set(name),
+            leader_id: Set(leader.id),
+            created_at: Set(Utc::now()),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+        leader.join_alliance(db, Some(a.id)).await?;
+        Ok(a)
+    }
+
+    pub async fn members<C: ConnectionTrait>(
+        &self,
+        db: &C,
+    ) -> Result<Vec<PublicPlayer>, OperationError> {
+        Ok(self
+            .find_related(player::Entity)
+            .order_by_asc(player::Column::Id)
+            .into_partial_model::<PublicPlayer>()
+            .all(db)
+            .await?)
+    }
+
+    pub async fn count_member<C: ConnectionTrait>(&self, db: &C) -> Result<u64, OperationError> {
+        Ok(self.find_related(player::Entity).count(db).await?)
+    }
+
+    /// Invite the player, an invitation already sent is returned as is.
+    pub async fn invite<C: ConnectionTrait>(
+        &self,
+        db: &C,
+        player_id: i32,
+    ) -> Result<invite::Model, OperationError> {
+        let p = player::Entity::find_by_id(player_id)
+            .one(db)
+            .await?
+            .ok_or(OperationError::PlayerNotExist(player_id))?;
+        if p.alliance_id == Some(self.id) {
+            return Err(OperationError::AlreadyInAlliance);
+        }
+        if let Some(i) = self
+            .find_related(invite::Entity)
+            .filter(invite::Column::PlayerId.eq(player_id))
+            .one(db)
+            .await?
+        {
+            return Ok(i);
+        }
+        Ok(invite::ActiveModel {
+            alliance_id: Set(self.id),
+            player_id: Set(player_id),
+            created_at: Set(Utc::now()),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?)
+    }
+
+    /// Let the invited player in, all the invitations to the player are dropped.
+    pub async fn accept<C: ConnectionTrait>(
+        &self,
+        db: &C,
+        player: player::Model,
+    ) -> Result<player::Model, OperationError> {
+        if player.alliance_id.is_some() {
+            return Err(OperationError::AlreadyInAlliance);
+        }
+        self.find_related(invite::Entity)
+            .filter(invite::Column::PlayerId.eq(player.id))
+            .one(db)
+            .await?
+            .ok_or(OperationError::InviteNotExist(self.id))?;
+        let max = config::rule().alliance.max_members;
+        if self.count_member(db).await? >= max {
+            return Err(OperationError::AllianceFull { max });
+        }
+
+        invite::Entity::delete_many()
+            .filter(invite::Column::PlayerId.eq(player.id))
+            .exec(db)
+            .await?;
+        player.join_alliance(db, Some(self.id)).await
+    }
+
+    /// Remove the member, the leadership passes to the earliest registered member left,
+    /// the alliance is dissolved when nobody is left.
+    pub async fn remove<C: ConnectionTrait>(
+        self,
+        db: &C,
+        member: player::Model,
+    ) -> Result<player::Model, OperationError> {
+        if member.alliance_id != Some(self.id) {
+            return Err(OperationError::NotAllianceMember(member.id));
+        }
+        let member = member.join_alliance(db, None).await?;
+        if self.leader_id != member.id {
+            return Ok(member);
+        }
+
+        match self
+            .find_related(player::Entity)
+            .order_by_asc(player::Column::Id)
+            .one(db)
+            .await?
+        {
+            Some(next) => {
+                let mut a = self.into_active_model();
+                a.leader_id = Set(next.id);
+                a.update(db).await?;
+            }
+            None => {
+                invite::Entity::delete_many()
+                    .filter(invite::Column::AllianceId.eq(self.id))
+                    .exec(db)
+                    .await?;
+                self.delete(db).await?;
+            }
+        }
+        Ok(member)
+    }
+}
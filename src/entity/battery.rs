@@ -0,0 +1,177 @@
+use chrono::Utc;
+use sea_orm::{entity::prelude::*, IntoActiveModel, Set};
+use serde::{Deserialize, Serialize};
+
+use crate::{config, err::OperationError};
+
+use super::alliance;
+
+/// Energy storage placed on a node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "battery")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    #[sea_orm(index)]
+    #[serde(
+        serialize_with = "entropy_base::grid::ser_flat",
+        deserialize_with = "entropy_base::grid::de_flat"
+    )]
+    pub pos: i32,
+    #[sea_orm(index)]
+    pub master_id: i32,
+    pub access: Access,
+    pub capacity: i64,
+    pub energy: i64,
+    /// when the leakage is last settled
+    pub leak_at: DateTimeUtc,
+}
+
+/// Who can deposit into and withdraw from a battery, the owner always can.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+#[serde(rename_all = "snake_case")]
+pub enum Access {
+    #[sea_orm(string_value = "private")]
+    Private,
+    /// the owner and the members of the owner's alliance
+    #[sea_orm(string_value = "alliance")]
+    Alliance,
+    #[sea_orm(string_value = "public")]
+    Public,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::player::Entity",
+        from = "Column::MasterId",
+        to = "super::player::Column::Id"
+    )]
+    Player,
+    #[sea_orm(
+        belongs_to = "super::node::Entity",
+        from = "Column::Pos",
+        to = "super::node::Column::Id"
+    )]
+    Node,
+}
+
+impl Related<super::player::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Player.def()
+    }
+}
+
+impl Related<super::node::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Node.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// Energy cost of building a battery with the capacity.
+pub fn get_build_cost(capacity: i64) -> i64 {
+    let per_energy = config::rule().battery.capacity_per_energy.max(1);
+    (capacity + per_energy - 1) / per_energy
+}
+
+impl Model {
+    pub async fn build<C: ConnectionTrait>(
+        db: &C,
+        pos: i32,
+        master_id: i32,
+        capacity: i64,
+        access: Access,
+    ) -> Result<Model, OperationError> {
+        let b = ActiveModel {
+            pos: Set(pos),
+            master_id: Set(master_id),
+            access: Set(access),
+            capacity: Set(capacity),
+            energy: Set(0),
+            leak_at: Set(Utc::now()),
+            ..Default::default()
+        };
+        Ok(b.insert(db).await?)
+    }
+
+    /// Leak the stored energy by the time elapsed since last settled,
+    /// the energy halves every `rule.battery.half_life` seconds.
+    ///
+    /// Database is only updated when the energy changes, so that frequent access
+    /// still accumulates the elapsed time, or when an empty battery is settled,
+    /// so that its idle time does not leak the next deposit.
+    pub async fn leak<C: ConnectionTrait>(self, db: &C) -> Result<Model, OperationError> {
+        let now = Utc::now();
+        let elapsed = (now - self.leak_at).num_milliseconds().max(0) as f64 / 1000.0;
+        let half_life = config::rule().battery.half_life;
+        let remain = if half_life > 0.0 {
+            0.5f64.powf(elapsed / half_life)
+        } else {
+            0.0
+        };
+        let energy = (self.energy as f64 * remain).round() as i64;
+        if energy == self.energy && (energy != 0 || elapsed == 0.0) {
+            return Ok(self);
+        }
+
+        let mut b = self.into_active_model();
+        b.energy = Set(energy);
+        b.leak_at = Set(now);
+        Ok(b.update(db).await?)
+    }
+
+    pub async fn accessible_by<C: ConnectionTrait>(
+        &self,
+        db: &C,
+        player_id: i32,
+    ) -> Result<bool, DbErr> {
+        Ok(match self.access {
+            _ if self.master_id == player_id => true,
+            Access::Private => false,
+            Access::Alliance => alliance::allied(db, self.master_id, player_id).await?,
+            Access::Public => true,
+        })
+    }
+
+    /// Settle the leakage and store the energy, the leakage restarts from now.
+    pub async fn deposit<C: ConnectionTrait>(
+        self,
+        db: &C,
+        energy: i64,
+    ) -> Result<Model, OperationError> {
+        let b = self.leak(db).await?;
+        let room = b.capacity - b.energy;
+        if energy > room {
+            return Err(OperationError::BatteryFull {
+                require: energy,
+                room,
+            });
+        }
+        let mut a = b.into_active_model();
+        a.energy = Set(b.energy + energy);
+        a.leak_at = Set(Utc::now());
+        Ok(a.update(db).await?)
+    }
+
+    /// Settle the leakage and take the energy out, the leakage restarts from now.
+    pub async fn withdraw<C: ConnectionTrait>(
+        self,
+        db: &C,
+        energy: i64,
+    ) -> Result<Model, OperationError> {
+        let b = self.leak(db).await?;
+        if energy > b.energy {
+            return Err(OperationError::EnergyNotEnough {
+                require: energy,
+                reserve: b.energy,
+            });
+        }
+        let mut a = b.into_active_model();
+        a.energy = Set(b.energy - energy);
+        a.leak_at = Set(Utc::now());
+        Ok(a.update(db).await?)
+    }
+}
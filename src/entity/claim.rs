@@ -0,0 +1,261 @@
+use chrono::{Duration, Utc};
+use sea_orm::{entity::prelude::*, IntoActiveModel, QueryOrder, Set};
+use serde::{Deserialize, Serialize};
+
+use crate::{config, err::OperationError};
+use entropy_base::grid::NodeID;
+
+use super::{alliance, guest, permit, region::Region};
+
+/// A node claimed by a player for a period, keyed by the node flat id.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "claim")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    #[serde(
+        serialize_with = "entropy_base::grid::ser_flat",
+        deserialize_with = "entropy_base::grid::de_flat"
+    )]
+    pub id: i32,
+    #[sea_orm(index)]
+    pub master_id: i32,
+    pub mode: Mode,
+    /// energy paid for the claim, a contester must pay more than what remains of it
+    pub stake: i64,
+    /// tax collected, waiting for the claimant
+    pub treasury: i64,
+    pub claimed_at: DateTimeUtc,
+    pub expire_at: DateTimeUtc,
+}
+
+/// What other players can do on a claimed node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    /// others cannot harvest or heat
+    #[sea_orm(string_value = "exclusive")]
+    Exclusive,
+    /// others pay tax to the claimant
+    #[sea_orm(string_value = "taxed")]
+    Taxed,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::player::Entity",
+        from = "Column::MasterId",
+        to = "super::player::Column::Id"
+    )]
+    Player,
+    #[sea_orm(
+        belongs_to = "super::node::Entity",
+        from = "Column::Id",
+        to = "super::node::Column::Id"
+    )]
+    Node,
+}
+
+impl Related<super::player::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Player.def()
+    }
+}
+
+impl Related<super::node::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Node.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// How a player may use a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Levy {
+    Free,
+    Taxed(Model),
+    Denied,
+}
+
+/// Energy cost of claiming a node for `duration` seconds.
+pub fn get_claim_cost(duration: i64) -> i64 {
+    let per_hour = config::rule().claim.cost_per_hour;
+    (duration * per_hour + 3599) / 3600
+}
+
+/// Tax charged on `energy`, rounded up.
+pub fn get_tax(energy: i64) -> i64 {
+    (energy as f64 * config::rule().claim.tax_rate).ceil() as i64
+}
+
+impl Model {
+    pub fn is_active(&self) -> bool {
+        self.expire_at > Utc::now()
+    }
+
+    /// The part of stake not consumed by time yet, rounded up.
+    pub fn remaining_stake(&self) -> i64 {
+        let now = Utc::now();
+        let total = (self.expire_at - self.claimed_at).num_seconds();
+        let remain = (self.expire_at - now).num_seconds();
+        if total <= 0 || remain <= 0 {
+            return 0;
+        }
+        (self.stake as f64 * remain as f64 / total as f64).ceil() as i64
+    }
+
+    /// Least stake to take the claim over.
+    pub fn contest_stake(&self) -> i64 {
+        let factor = config::rule().claim.contest_factor;
+        (self.remaining_stake() as f64 * factor).ceil() as i64 + 1
+    }
+
+    /// Active claim on the node.
+    pub async fn find_active<C: ConnectionTrait>(
+        db: &C,
+        node: NodeID,
+    ) -> Result<Option<Model>, OperationError> {
+        Ok(Entity::find_by_id(node.into_i32())
+            .one(db)
+            .await?
+            .filter(|c| c.is_active()))
+    }
+
+    /// How the player may use the node,
+    /// allies of the claimant and players holding its permit use it freely.
+    pub async fn levy<C: ConnectionTrait>(
+        db: &C,
+        node: NodeID,
+        player_id: i32,
+    ) -> Result<Levy, OperationError> {
+        let Some(c) = Self::find_active(db, node).await? else {
+            return Ok(Levy::Free);
+        };
+        if c.master_id == player_id
+            || alliance::allied(db, c.master_id, player_id).await?
+            || permit::permitted(db, node, player_id, c.master_id).await?
+        {
+            return Ok(Levy::Free);
+        }
+        match c.mode {
+            Mode::Exclusive => Ok(Levy::Denied),
+            Mode::Taxed => Ok(Levy::Taxed(c)),
+        }
+    }
+
+    /// Claim the node with `stake` energy, the claim lasts `duration` seconds.
+    ///
+    /// Own active claim is extended, but never beyond `rule.claim.max_duration` from now,
+    /// and only the part of `stake` for the time actually added is paid.
+    /// Others' active claim is taken over only if `stake` reaches its contest stake,
+    /// the tax left in it is forfeited; the tax left in an expired claim goes to the
+    /// first guest of its previous claimant. Active claims of allies are never contested.
+    ///
+    /// Return the claim and the energy paid.
+    pub async fn claim<C: ConnectionTrait>(
+        db: &C,
+        node: NodeID,
+        player_id: i32,
+        mode: Mode,
+        duration: i64,
+        stake: i64,
+    ) -> Result<(Model, i64), OperationError> {
+        let now = Utc::now();
+        let max = Duration::seconds(config::rule().claim.max_duration);
+        let duration = Duration::seconds(duration);
+
+        let Some(c) = Entity::find_by_id(node.into_i32()).one(db).await? else {
+            let c = ActiveModel {
+                id: Set(node.into_i32()),
+                master_id: Set(player_id),
+                mode: Set(mode),
+                stake: Set(stake),
+                treasury: Set(0),
+                claimed_at: Set(now),
+                expire_at: Set(now + duration),
+            };
+            return Ok((c.insert(db).await?, stake));
+        };
+
+        let mut a = c.into_active_model();
+        a.mode = Set(mode);
+        let mut paid = stake;
+        if c.is_active() && c.master_id == player_id {
+            let expire_at = (c.expire_at + duration).min(now + max);
+            let added = (expire_at - c.expire_at).num_seconds().max(0);
+            paid = (stake as f64 * added as f64 / duration.num_seconds() as f64).ceil() as i64;
+            a.stake = Set(c.stake + paid);
+            a.expire_at = Set(expire_at);
+        } else {
+            if c.is_active() && alliance::allied(db, c.master_id, player_id).await? {
+                return Err(OperationError::ClaimAllied(node));
+            }
+            if c.is_active() && stake < c.contest_stake() {
+                return Err(OperationError::ClaimContestFailed {
+                    node,
+                    require: c.contest_stake(),
+                    offer: stake,
+                });
+            }
+            // a new claim, tax left to the previous claimant is kept only if it is the same player
+            if c.master_id != player_id {
+                if !c.is_active() && c.treasury > 0 {
+                    Self::refund_treasury(db, &c).await?;
+                }
+                a.treasury = Set(0);
+            }
+            a.master_id = Set(player_id);
+            a.stake = Set(stake);
+            a.claimed_at = Set(now);
+            a.expire_at = Set(now + duration);
+        }
+        Ok((a.update(db).await?, paid))
+    }
+
+    /// Pay the tax left in an expired claim to the first guest of its claimant,
+    /// lost if the claimant has no guest any more.
+    async fn refund_treasury<C: ConnectionTrait>(db: &C, c: &Model) -> Result<(), OperationError> {
+        let g = guest::Entity::find()
+            .filter(guest::Column::MasterId.eq(c.master_id))
+            .order_by_asc(guest::Column::Id)
+            .one(db)
+            .await?;
+        if let Some(g) = g {
+            g.gain_energy(db, c.treasury).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn pay_tax<C: ConnectionTrait>(
+        self,
+        db: &C,
+        energy: i64,
+    ) -> Result<Model, OperationError> {
+        let mut a = self.into_active_model();
+        a.treasury = Set(self.treasury + energy);
+        Ok(a.update(db).await?)
+    }
+
+    /// Take all the tax out.
+    pub async fn collect<C: ConnectionTrait>(self, db: &C) -> Result<(Model, i64), OperationError> {
+        let energy = self.treasury;
+        let mut a = self.into_active_model();
+        a.treasury = Set(0);
+        Ok((a.update(db).await?, energy))
+    }
+
+    /// Active claims inside the region.
+    pub async fn list_in_region<C: ConnectionTrait>(
+        db: &C,
+        region: Region,
+    ) -> Result<Vec<Model>, OperationError> {
+        Ok(Entity::find()
+            .filter(region.condition(Column::Id))
+            .filter(Column::ExpireAt.gt(Utc::now()))
+            .order_by_asc(Column::Id)
+            .all(db)
+            .await?)
+    }
+}
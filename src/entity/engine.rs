@@ -0,0 +1,214 @@
+use chrono::{Duration, Utc};
+use sea_orm::{entity::prelude::*, DatabaseTransaction, IntoActiveModel, Set, Unchanged};
+use serde::{Deserialize, Serialize};
+
+use crate::{config, err::OperationError};
+use entropy_base::grid::NodeID;
+
+use super::{claim, guest::carnot_exchange, node};
+
+/// A heat engine built on a node, harvests between two cells by itself.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "engine")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    #[sea_orm(index)]
+    #[serde(
+        serialize_with = "entropy_base::grid::ser_flat",
+        deserialize_with = "entropy_base::grid::de_flat"
+    )]
+    pub pos: i32,
+    #[sea_orm(index)]
+    pub master_id: i32,
+    pub cell: i32,
+    /// the other cell, or the node mean if None
+    pub other: Option<i32>,
+    /// harvested energy waiting to be collected
+    pub energy: i64,
+    /// when the last cycle finished
+    pub run_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::player::Entity",
+        from = "Column::MasterId",
+        to = "super::player::Column::Id"
+    )]
+    Player,
+    #[sea_orm(
+        belongs_to = "super::node::Entity",
+        from = "Column::Pos",
+        to = "super::node::Column::Id"
+    )]
+    Node,
+}
+
+impl Related<super::player::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Player.def()
+    }
+}
+
+impl Related<super::node::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Node.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl Model {
+    pub async fn build<C: ConnectionTrait>(
+        db: &C,
+        node: &node::Model,
+        master_id: i32,
+        cell: usize,
+        other: Option<usize>,
+    ) -> Result<Model, OperationError> {
+        let node_id = NodeID::from_i32(node.id);
+        for i in std::iter::once(cell).chain(other) {
+            if i >= node.data.len() {
+                return Err(OperationError::CellIndexOutOfRange {
+                    node: node_id,
+                    require: i,
+                    max: node.data.len(),
+                });
+            }
+        }
+        if other == Some(cell) {
+            return Err(OperationError::SameCell {
+                node: node_id,
+                index: cell,
+            });
+        }
+
+        let e = ActiveModel {
+            pos: Set(node.id),
+            master_id: Set(master_id),
+            cell: Set(cell as i32),
+            other: Set(other.map(|i| i as i32)),
+            energy: Set(0),
+            run_at: Set(Utc::now()),
+            ..Default::default()
+        };
+        Ok(e.insert(db).await?)
+    }
+
+    /// Run all engines on the node for the cycles elapsed,
+    /// return the node after the engines run.
+    ///
+    /// Engines of players denied by the claim on the node stay idle,
+    /// engines of taxed players pay the tax on the energy harvested.
+    pub async fn run_on_node(
+        txn: &DatabaseTransaction,
+        node: node::Model,
+    ) -> Result<node::Model, OperationError> {
+        let engines = Entity::find()
+            .filter(Column::Pos.eq(node.id))
+            .all(txn)
+            .await?;
+
+        let now = Utc::now();
+        let rule = &config::rule().engine;
+        let tick = rule.tick.max(1);
+
+        let mut data = node.data.clone();
+        // engines only move heat between cells, so the mean stays the same
+        let mean = node::mean_of(&data).unwrap_or_default().round() as i8;
+        let mut changed = false;
+        for e in engines {
+            let cycles = ((now - e.run_at).num_seconds() / tick).max(0);
+            if cycles == 0 {
+                continue;
+            }
+            let mut harvested = 0;
+            match claim::Model::levy(txn, NodeID::from_i32(node.id), e.master_id).await? {
+                claim::Levy::Denied => {}
+                claim::Levy::Free => {
+                    harvested = e.run(&mut data, mean, cycles.min(rule.max_cycles));
+                }
+                claim::Levy::Taxed(c) => {
+                    harvested = e.run(&mut data, mean, cycles.min(rule.max_cycles));
+                    let tax = claim::get_tax(harvested).min(harvested);
+                    if tax > 0 {
+                        c.pay_tax(txn, tax).await?;
+                    }
+                    harvested -= tax;
+                }
+            }
+
+            let mut a = e.into_active_model();
+            a.energy = Set(e.energy + harvested);
+            a.run_at = Set(e.run_at + Duration::seconds(tick * cycles));
+            a.update(txn).await?;
+            changed = true;
+        }
+
+        if !changed {
+            return Ok(node);
+        }
+        let n = node::ActiveModel {
+            id: Unchanged(node.id),
+            data: Set(data),
+            touched_at: Set(now),
+            ..Default::default()
+        };
+        Ok(n.update(txn).await?)
+    }
+
+    /// Run the carnot cycle on the cells at most `cycles` times, against `mean`
+    /// if there is no other cell, stop when no more energy could be harvested
+    /// or the other cells cannot take the heat any more.
+    ///
+    /// Return the energy harvested.
+    fn run(&self, data: &mut [u8], mean: i8, cycles: i64) -> i64 {
+        let cell = self.cell as usize;
+        if cell >= data.len() || self.other.is_some_and(|i| i as usize >= data.len()) {
+            return 0;
+        }
+
+        let mut harvested = 0;
+        for _ in 0..cycles {
+            let one = data[cell] as i8;
+            let delta = match self.other {
+                Some(other) => {
+                    let other = other as usize;
+                    let (one, two, delta) = carnot_exchange(one, data[other] as i8);
+                    data[cell] = one as u8;
+                    data[other] = two as u8;
+                    delta
+                }
+                None => {
+                    let (next, _, delta) = carnot_exchange(one, mean);
+                    let change = next as i16 - one as i16;
+                    let left = node::spread(data, cell, -change);
+                    // heat the other cells cannot take stays in the cell,
+                    // and only the part of the cycle done is harvested
+                    data[cell] = (one as i16 + change + left) as i8 as u8;
+                    if left != 0 {
+                        let done = (change + left) as i64;
+                        harvested += delta as i64 * done / change as i64;
+                        break;
+                    }
+                    delta
+                }
+            };
+            if delta == 0 {
+                break;
+            }
+            harvested += delta as i64;
+        }
+        harvested
+    }
+
+    /// Take all the stored energy out.
+    pub async fn collect<C: ConnectionTrait>(self, db: &C) -> Result<(Model, i64), OperationError> {
+        let energy = self.energy;
+        let mut e = self.into_active_model();
+        e.energy = Set(0);
+        Ok((e.update(db).await?, energy))
+    }
+}
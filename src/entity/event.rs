@@ -0,0 +1,210 @@
+use std::time::Duration as StdDuration;
+
+use chrono::{Duration, Utc};
+use rand::{rngs::SmallRng, seq::IteratorRandom, SeedableRng};
+use sea_orm::{
+    entity::prelude::*, sea_query::Expr, DbConn, IntoActiveModel, Iterable, Order, QueryOrder,
+    QuerySelect, Set, TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{info, instrument, warn};
+
+use crate::{config, err::OperationError, world};
+use entropy_base::grid::NodeID;
+
+use super::{guest, node, region::Region};
+
+/// An event perturbing a region of the world, announced before it happens.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "world_event")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub kind: Kind,
+    pub x0: i16,
+    pub y0: i16,
+    pub x1: i16,
+    pub y1: i16,
+    /// degrees for the solar flare and the cold front, energy for the bonus
+    pub magnitude: i64,
+    pub announced_at: DateTimeUtc,
+    #[sea_orm(index)]
+    pub start_at: DateTimeUtc,
+    /// last node applied, the event is applied batch by batch
+    #[serde(skip)]
+    pub cursor: Option<i32>,
+    /// None until the event is applied on all the nodes
+    #[sea_orm(index)]
+    pub applied_at: Option<DateTimeUtc>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    /// heat all the cells around a node
+    #[sea_orm(string_value = "solar_flare")]
+    SolarFlare,
+    /// cool all the cells of a band of nodes
+    #[sea_orm(string_value = "cold_front")]
+    ColdFront,
+    /// energy for every guest on a node
+    #[sea_orm(string_value = "bonus")]
+    Bonus,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// Apply the due events and announce the next one every `config.interval` seconds, forever.
+pub async fn schedule_periodically(db: DbConn, config: config::Event) {
+    let mut ticker = tokio::time::interval(StdDuration::from_secs(config.interval.max(1)));
+    loop {
+        ticker.tick().await;
+        if let Err(e) = schedule(&db, &config).await {
+            warn!("world event scheduling failed <- {e}");
+        }
+    }
+}
+
+#[instrument(skip(db, config), err)]
+pub async fn schedule(db: &DbConn, config: &config::Event) -> Result<(), OperationError> {
+    let pending = Entity::find()
+        .filter(Column::AppliedAt.is_null())
+        .order_by_asc(Column::StartAt)
+        .all(db)
+        .await?;
+    let mut announced = false;
+    for e in pending {
+        if e.start_at <= Utc::now() {
+            e.apply(db, config.batch).await?;
+        } else {
+            announced = true;
+        }
+    }
+    if !announced {
+        Model::announce(db, config).await?;
+    }
+    Ok(())
+}
+
+/// Events still to happen and the latest happened, the latest first.
+pub async fn list<C: ConnectionTrait>(db: &C, limit: u64) -> Result<Vec<Model>, DbErr> {
+    Entity::find()
+        .order_by_desc(Column::StartAt)
+        .limit(limit)
+        .all(db)
+        .await
+}
+
+impl Model {
+    /// Region of the event as stored, see [`world::fit_region`].
+    pub fn region(&self) -> Region {
+        Region {
+            x0: self.x0,
+            y0: self.y0,
+            x1: self.x1,
+            y1: self.y1,
+        }
+    }
+
+    /// Announce an event of a random kind, around a random node of the world.
+    pub async fn announce<C: ConnectionTrait>(
+        db: &C,
+        config: &config::Event,
+    ) -> Result<Model, OperationError> {
+        let at = node::Entity::find()
+            .order_by(Expr::cust("random()"), Order::Asc)
+            .one(db)
+            .await?
+            .and_then(|n| world::locate(NodeID::from_i32(n.id)).ok())
+            .unwrap_or(NodeID::ORIGIN);
+        let kind = Kind::iter()
+            .choose(&mut SmallRng::from_entropy())
+            .unwrap_or(Kind::Bonus);
+        let (x, y) = (at.0 as i32, at.1 as i32);
+        let (xs, ys, magnitude) = match kind {
+            Kind::SolarFlare => {
+                let r = config.flare_radius.max(0) as i32;
+                ((x - r, x + r), (y - r, y + r), config.flare_heat as i64)
+            }
+            Kind::ColdFront => {
+                let (w, l) = (
+                    config.front_width.max(1) as i32,
+                    config.front_length.max(1) as i32,
+                );
+                (
+                    (x - l / 2, x + (l - 1) / 2),
+                    (y - w / 2, y + (w - 1) / 2),
+                    -(config.front_cool as i64),
+                )
+            }
+            Kind::Bonus => ((x, x), (y, y), config.bonus_energy),
+        };
+        let region = world::fit_region(xs, ys);
+        let now = Utc::now();
+        let e = ActiveModel {
+            kind: Set(kind),
+            x0: Set(region.x0),
+            y0: Set(region.y0),
+            x1: Set(region.x1),
+            y1: Set(region.y1),
+            magnitude: Set(magnitude),
+            announced_at: Set(now),
+            start_at: Set(now + Duration::seconds(config.notice)),
+            cursor: Set(None),
+            applied_at: Set(None),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+        info!("world event announced: {e:?}");
+        Ok(e)
+    }
+
+    /// Apply the event, one transaction per batch of nodes,
+    /// an event interrupted goes on from the last batch next time.
+    pub async fn apply(self, db: &DbConn, batch: u64) -> Result<Model, OperationError> {
+        let mut e = self;
+        loop {
+            let txn = db.begin().await?;
+            let done = match e.kind {
+                Kind::SolarFlare | Kind::ColdFront => {
+                    let delta = e.magnitude.clamp(i16::MIN as i64, i16::MAX as i64) as i16;
+                    let regions = world::split_region(e.region());
+                    let last =
+                        node::Model::shift_batch(&txn, &regions, delta, e.cursor, batch).await?;
+                    let mut a = e.into_active_model();
+                    a.cursor = Set(last.or(e.cursor));
+                    e = a.update(&txn).await?;
+                    last.is_none()
+                }
+                Kind::Bonus => {
+                    let gs = guest::Entity::find()
+                        .filter(Region::any_condition(
+                            &world::split_region(e.region()),
+                            guest::Column::Pos,
+                        ))
+                        .all(&txn)
+                        .await?;
+                    for g in gs {
+                        g.gain_energy(&txn, e.magnitude).await?;
+                    }
+                    true
+                }
+            };
+            if done {
+                let mut a = e.into_active_model();
+                a.applied_at = Set(Some(Utc::now()));
+                e = a.update(&txn).await?;
+            }
+            txn.commit().await?;
+            if done {
+                info!("world event applied: {e:?}");
+                return Ok(e);
+            }
+        }
+    }
+}
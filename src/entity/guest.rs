@@ -1,13 +1,18 @@
 use axum::async_trait;
+use chrono::Utc;
 use ordered_float::NotNan;
 use sea_orm::{
-    entity::prelude::*, ActiveValue::NotSet, Condition, DatabaseTransaction, IntoActiveModel, Set,
-    Unchanged,
+    entity::prelude::*, ActiveValue::NotSet, Condition, DatabaseTransaction, IntoActiveModel,
+    JoinType, QuerySelect, Set, Unchanged,
 };
 use serde::{Deserialize, Serialize};
 
-use crate::err::{ModelError, OperationError};
-use entropy_base::grid::{navi, FlatID, Node, NodeID};
+use crate::{
+    config,
+    err::{ModelError, OperationError},
+    world,
+};
+use entropy_base::grid::{navi, FlatID, Node, NodeID, INDEXED_NAVI};
 
 use super::{node, variant::DetectedGuest};
 
@@ -24,6 +29,9 @@ pub struct Model {
     )]
     pub pos: i32,
     pub temperature: i16, // should be i8, but sea_orm always error
+    /// when the temperature is last settled with the node
+    #[sea_orm(default_expr = "Expr::current_timestamp()")]
+    pub thermal_at: DateTimeUtc,
     #[sea_orm(index)]
     pub master_id: i32,
 }
@@ -63,7 +71,7 @@ impl ActiveModelBehavior for ActiveModel {
         C: ConnectionTrait,
     {
         let pos = self.pos.as_ref();
-        node::Model::_ensure(db, FlatID::from(pos.clone()))
+        node::Model::_ensure(db, FlatID::from(*pos))
             .await
             .map_err(|e| DbErr::Custom(e.to_string()))?;
         Ok(self)
@@ -83,6 +91,65 @@ pub fn get_carnot_efficiency(one: i8, other: i8) -> f32 {
     1f32 - c / h
 }
 
+/// Run a carnot engine between two heat sources once.
+///
+/// Return the new temperatures of both sources and the energy harvested.
+pub fn carnot_exchange(one: i8, other: i8) -> (i8, i8, u8) {
+    // Calculate the delta energy first
+    let delta = one.abs_diff(other);
+    let delta = (get_carnot_efficiency(one, other) * delta as f32).div_euclid(2.0) as u8;
+
+    // no overflow will happen, the efficiency proves that, so no need to check
+
+    // Determine which temperature is hotter and colder.
+    // and go change
+    if one > other {
+        (
+            one.saturating_sub_unsigned(delta),
+            other.saturating_add_unsigned(delta),
+            delta,
+        )
+    } else if one < other {
+        (
+            one.saturating_add_unsigned(delta),
+            other.saturating_sub_unsigned(delta),
+            delta,
+        )
+    } else {
+        (one, other, delta)
+    }
+}
+
+/// Coefficient of performance of a heat pump moving heat from `cold` into `hot`,
+/// the heat removed from `cold` per energy spent.
+///
+/// Pumping is free when `hot` is not hotter, so the COP is capped by `rule.cool.max_cop`.
+pub fn get_cooling_cop(cold: i8, hot: i8) -> f32 {
+    let c = get_kelvin(cold) as f32;
+    let h = get_kelvin(hot) as f32;
+    let max_cop = config::rule().cool.max_cop;
+    if h > c {
+        (c / (h - c)).min(max_cop)
+    } else {
+        max_cop
+    }
+}
+
+/// Energy cost of walking towards `to`.
+///
+/// Return None if the direction is not one of the 8 neighbours.
+pub fn get_walk_cost(to: navi::Direction) -> Option<i64> {
+    if to == navi::SITU || !INDEXED_NAVI.contains(&to) {
+        return None;
+    }
+    let rule = &config::rule().walk;
+    if to.0 == 0 || to.1 == 0 {
+        Some(rule.straight_cost)
+    } else {
+        Some(rule.diagonal_cost)
+    }
+}
+
 fn get_kelvin(value: i8) -> u8 {
     if value < 0 {
         (value - i8::MIN) as u8
@@ -102,6 +169,7 @@ impl Model {
             energy: Set(0),
             pos: Set(pos.into_i32()),
             temperature: Set(0),
+            thermal_at: Set(Utc::now()),
             master_id: Set(master_id),
         };
         Ok(g.insert(db).await?)
@@ -112,12 +180,22 @@ impl Model {
         db: &C,
         to: navi::Direction,
     ) -> Result<Model, OperationError> {
-        self.verify_energy(1)?;
+        let at = world::step(FlatID::from(self.pos).into_node_id(), to)?;
+        // nodes not generated yet take the terrain they will be generated with
+        let terrain = match node::Entity::find_by_id(at.into_i32()).one(db).await? {
+            Some(n) => n.terrain,
+            None => world::terrain(at),
+        };
+        let cost = get_walk_cost(to).ok_or(OperationError::DirectionNotAllowed(to))?
+            + terrain
+                .walk_cost()
+                .ok_or(OperationError::NodeImpassable(at))?;
+        self.verify_energy(cost)?;
 
-        let at = FlatID::from(self.pos).into_node_id().navi_to(to);
         let mut g = self.into_active_model();
         g.pos = Set(at.into_i32());
-        g.energy = Set(self.energy - 1);
+        g.energy = Set(self.energy - cost);
+        g.thermal_at = Set(Utc::now()); // start settling with the new node
         Ok(g.update(db).await?)
     }
 
@@ -138,6 +216,7 @@ impl Model {
             energy: Set(transfer_energy),
             pos: Set(self.pos),
             temperature: Set(0),
+            thermal_at: Set(Utc::now()),
             master_id: Set(self.master_id),
             ..Default::default()
         };
@@ -157,12 +236,46 @@ impl Model {
                     .add(Column::Id.ne(self.id))
                     .add(Column::Pos.eq(self.pos)),
             )
+            .join(JoinType::InnerJoin, Relation::Player.def())
             .into_partial_model::<DetectedGuest>()
             .all(db)
             .await?;
         Ok(gs)
     }
 
+    /// Drift the temperature toward the mean temperature of the node,
+    /// the difference halves every `rule.thermal.half_life` seconds,
+    /// scaled by the terrain of the node.
+    ///
+    /// Database is only updated when the temperature changes, so that
+    /// frequent access still accumulates the elapsed time.
+    pub async fn equalize<C: ConnectionTrait>(self, db: &C) -> Result<Model, OperationError> {
+        let Some(n) = node::Entity::find_by_id(self.pos).one(db).await? else {
+            return Ok(self);
+        };
+        let Some(mean) = n.mean_temperature() else {
+            return Ok(self);
+        };
+
+        let now = Utc::now();
+        let elapsed = (now - self.thermal_at).num_milliseconds().max(0) as f32 / 1000.0;
+        let half_life = config::rule().thermal.half_life * n.terrain.thermal_factor();
+        let remain = if half_life > 0.0 {
+            0.5f32.powf(elapsed / half_life)
+        } else {
+            0.0
+        };
+        let temp = (mean + (self.temperature as f32 - mean) * remain).round() as i16;
+        if temp == self.temperature {
+            return Ok(self);
+        }
+
+        let mut g = self.into_active_model();
+        g.temperature = Set(temp);
+        g.thermal_at = Set(now);
+        Ok(g.update(db).await?)
+    }
+
     /// Consume energy of self energy and update database.
     pub async fn consume_energy<C: ConnectionTrait>(
         &self,
@@ -178,6 +291,17 @@ impl Model {
         Ok(g)
     }
 
+    /// Add energy to self and update database.
+    pub async fn gain_energy<C: ConnectionTrait>(
+        &self,
+        db: &C,
+        energy: i64,
+    ) -> Result<Model, OperationError> {
+        let mut g = self.into_active_model();
+        g.energy = Set(self.energy + energy);
+        Ok(g.update(db).await?)
+    }
+
     pub fn get_efficiency(&self, cell: i8) -> f32 {
         get_carnot_efficiency(self.temperature as i8, cell)
     }
@@ -186,10 +310,11 @@ impl Model {
     pub fn _harvest_active_model(
         self,
         node: Node,
+        terrain: node::Terrain,
         cell_i: usize,
     ) -> Result<(self::ActiveModel, node::ActiveModel), ModelError> {
-        let mut data = node.data.clone();
-        let mut cell = node.data.get(cell_i).ok_or(ModelError::Parse {
+        let mut data: Vec<u8> = node.data.clone().into();
+        let cell = node.data.get(cell_i).ok_or(ModelError::Parse {
             desc: format!(
                 "request length({1}) out of range <- node({0:?})",
                 node.id, cell_i
@@ -198,29 +323,84 @@ impl Model {
 
         let mut g = self.into_active_model();
 
-        // Calculate the delta energy first
+        let cell_before = cell;
+        let (temp, cell, delta) = carnot_exchange(self.temperature as i8, cell);
+        g.temperature = Set(temp as i16);
+        g.energy = Set(self.energy + delta as i64);
+        g.thermal_at = Set(Utc::now());
+        terrain.recharge(&mut data, cell_i, cell_before, cell);
+        let n = node::ActiveModel {
+            id: Unchanged(node.id.into_i32()),
+            data: Set(data),
+            touched_at: Set(Utc::now()),
+            ..Default::default()
+        };
+        Ok((g, n))
+    }
+
+    // generate two middle model for cooling a cell, pumped heat and the energy spent
+    // goes into the guest, the rest that guest cannot hold goes to other cells of the node
+    pub fn _cool_active_model(
+        self,
+        node: node::Model,
+        cell_i: usize,
+        energy: i64,
+    ) -> Result<(self::ActiveModel, node::ActiveModel), OperationError> {
+        self.verify_energy(energy)?;
+        let work: u8 = energy.try_into().map_err(|_| ModelError::OutOfLimit {
+            desc: "cool energy".to_string(),
+            limit_type: "u8",
+        })?;
+
+        let node_id = NodeID::from_i32(node.id);
+        let mut data = node.data;
+        let len = data.len();
+        let cell = *data
+            .get(cell_i)
+            .ok_or(OperationError::CellIndexOutOfRange {
+                node: node_id,
+                require: cell_i,
+                max: len,
+            })? as i8;
+
+        // heat removed from the cell, never below 0K, only the work needed for it is spent
         let temp = self.temperature as i8;
-        let delta = temp.abs_diff(cell);
-        let delta = (self.get_efficiency(cell) * delta as f32).div_euclid(2.0) as u8;
-
-        // no overflow will happen, the efficiency proves that, so no need to check
-
-        // Determine which temperature is hotter and colder.
-        // and go change
-        if temp > cell {
-            g.temperature = Set(temp.saturating_sub_unsigned(delta) as i16);
-            cell = cell.saturating_add_unsigned(delta);
-        } else if temp < cell {
-            g.temperature = Set(temp.saturating_add_unsigned(delta) as i16);
-            cell = cell.saturating_sub_unsigned(delta);
+        let cop = get_cooling_cop(cell, temp);
+        let headroom = get_kelvin(cell);
+        let work = if headroom == 0 || cop <= 0.0 {
+            0
         } else {
-            ()
+            work.min((headroom as f32 / cop).ceil().min(u8::MAX as f32) as u8)
         };
-        g.energy = Set(self.energy + delta as i64);
-        data.set(cell_i, cell);
+        let removed = ((cop * work as f32) as u16).min(headroom as u16) as u8;
+        data[cell_i] = cell.saturating_sub_unsigned(removed) as u8;
+
+        // work turns into heat as well
+        let mut rest = removed as u16 + work as u16;
+        let absorbed = rest.min(i8::MAX.abs_diff(temp) as u16) as u8;
+        rest -= absorbed as u16;
+        for i in (cell_i + 1..len).chain(0..cell_i) {
+            if rest == 0 {
+                break;
+            }
+            let other = data[i] as i8;
+            let dumped = rest.min(i8::MAX.abs_diff(other) as u16) as u8;
+            data[i] = other.saturating_add_unsigned(dumped) as u8;
+            rest -= dumped as u16;
+        }
+        if rest > 0 {
+            return Err(OperationError::NodeTemperatureTooHigh(node_id));
+        }
+
+        let mut g = self.into_active_model();
+        g.temperature = Set(temp.saturating_add_unsigned(absorbed) as i16);
+        g.thermal_at = Set(Utc::now());
+        g.energy = Set(self.energy - work as i64);
         let n = node::ActiveModel {
-            id: Unchanged(node.id.into_i32()),
-            data: Set(data.into()),
+            id: Unchanged(node.id),
+            data: Set(data),
+            touched_at: Set(Utc::now()),
+            ..Default::default()
         };
         Ok((g, n))
     }
@@ -229,12 +409,12 @@ impl Model {
     ///
     /// Return Ok(()) if energy is enough
     /// Return Err if energy is not enough
-    fn verify_energy(&self, require: i64) -> Result<(), OperationError> {
+    pub fn verify_energy(&self, require: i64) -> Result<(), OperationError> {
         if self.energy >= require {
             Ok(())
         } else {
             Err(OperationError::EnergyNotEnough {
-                require: require,
+                require,
                 reserve: self.energy,
             })
         }
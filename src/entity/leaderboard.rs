@@ -0,0 +1,123 @@
+use std::time::Duration;
+
+use sea_orm::{entity::prelude::*, DbConn, QueryOrder, QuerySelect, Statement, TransactionTrait};
+use serde::{Deserialize, Serialize};
+use tracing::{instrument, warn};
+
+use crate::err::{ModelError, OperationError};
+
+use super::variant::Ranked;
+
+/// Standings of a player, materialized from the guests and the visit log.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "leaderboard")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub player_id: i32,
+    pub name: String,
+    /// energy of all the guests
+    #[sea_orm(index)]
+    pub energy: i64,
+    #[sea_orm(index)]
+    pub guests: i64,
+    /// distinct nodes visited
+    #[sea_orm(index)]
+    pub visited: i64,
+    pub refreshed_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// What players are ranked by.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rank {
+    #[default]
+    Energy,
+    Guests,
+    Visited,
+}
+
+impl Rank {
+    fn column(self) -> Column {
+        match self {
+            Rank::Energy => Column::Energy,
+            Rank::Guests => Column::Guests,
+            Rank::Visited => Column::Visited,
+        }
+    }
+}
+
+/// Recompute the whole leaderboard in one transaction.
+///
+/// The guests standing now are logged as visits first,
+/// so the nodes of guests from before the visit log are counted too.
+#[instrument(skip(db), err)]
+pub async fn refresh(db: &DbConn) -> Result<(), DbErr> {
+    let txn = db.begin().await?;
+    let backend = txn.get_database_backend();
+    for sql in [
+        "INSERT INTO visit (player_id, pos, visited_at) \
+         SELECT DISTINCT master_id, pos, now() FROM guest \
+         ON CONFLICT DO NOTHING",
+        "DELETE FROM leaderboard",
+        "INSERT INTO leaderboard (player_id, name, energy, guests, visited, refreshed_at) \
+         SELECT p.id, p.name, COALESCE(g.energy, 0), COALESCE(g.guests, 0), COALESCE(v.visited, 0), now() \
+         FROM player p \
+         LEFT JOIN (SELECT master_id, SUM(energy)::bigint AS energy, COUNT(*) AS guests \
+                    FROM guest GROUP BY master_id) g ON g.master_id = p.id \
+         LEFT JOIN (SELECT player_id, COUNT(*) AS visited \
+                    FROM visit GROUP BY player_id) v ON v.player_id = p.id",
+    ] {
+        txn.execute(Statement::from_string(backend, sql)).await?;
+    }
+    txn.commit().await
+}
+
+/// Refresh the leaderboard every `interval` seconds, forever.
+pub async fn refresh_periodically(db: DbConn, interval: u64) {
+    let mut ticker = tokio::time::interval(Duration::from_secs(interval.max(1)));
+    loop {
+        ticker.tick().await;
+        if let Err(e) = refresh(&db).await {
+            warn!("leaderboard refresh failed <- {e}");
+        }
+    }
+}
+
+/// One page of the leaderboard, ties broken by the earlier registered player.
+pub async fn page<C: ConnectionTrait>(
+    db: &C,
+    by: Rank,
+    page: u64,
+    size: u64,
+) -> Result<Vec<Ranked>, OperationError> {
+    // postgres takes the offset as a bigint
+    let offset = page
+        .checked_mul(size)
+        .filter(|&o| o <= i64::MAX as u64)
+        .ok_or_else(|| {
+            OperationError::Model(ModelError::OutOfLimit {
+                desc: format!("page {page} of size {size}"),
+                limit_type: "entry",
+            })
+        })?;
+    let ms = Entity::find()
+        .order_by_desc(by.column())
+        .order_by_asc(Column::PlayerId)
+        .offset(offset)
+        .limit(size)
+        .all(db)
+        .await?;
+    Ok(ms
+        .into_iter()
+        .enumerate()
+        .map(|(i, entry)| Ranked {
+            rank: offset + i as u64 + 1,
+            entry,
+        })
+        .collect())
+}
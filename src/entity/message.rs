@@ -0,0 +1,120 @@
+use chrono::{Duration, Utc};
+use sea_orm::{entity::prelude::*, QueryOrder, QuerySelect, Set};
+use serde::{Deserialize, Serialize};
+
+use crate::{config, err::OperationError, world};
+use entropy_base::grid::NodeID;
+
+use super::{guest, region::Region};
+
+/// A message posted by a guest on a node, readable by guests within its radius.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "message")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    #[sea_orm(index)]
+    #[serde(
+        serialize_with = "entropy_base::grid::ser_flat",
+        deserialize_with = "entropy_base::grid::de_flat"
+    )]
+    pub pos: i32,
+    pub sender_id: i32,
+    pub sender_master_id: i32,
+    pub content: String,
+    /// nodes away the message reaches, 0 for the node only
+    pub radius: i16,
+    #[sea_orm(index)]
+    pub created_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::node::Entity",
+        from = "Column::Pos",
+        to = "super::node::Column::Id"
+    )]
+    Node,
+}
+
+impl Related<super::node::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Node.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// Energy cost of broadcasting a message to `radius` nodes away.
+pub fn get_broadcast_cost(radius: i16) -> i64 {
+    config::rule().chat.broadcast_cost * radius as i64
+}
+
+impl Model {
+    /// Post the message, then drop the expired ones and the oldest beyond the node limit.
+    pub async fn post<C: ConnectionTrait>(
+        db: &C,
+        sender: &guest::Model,
+        content: String,
+        radius: i16,
+    ) -> Result<Model, OperationError> {
+        let rule = &config::rule().chat;
+        let now = Utc::now();
+        let m = ActiveModel {
+            pos: Set(sender.pos),
+            sender_id: Set(sender.id),
+            sender_master_id: Set(sender.master_id),
+            content: Set(content),
+            radius: Set(radius),
+            created_at: Set(now),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+
+        // retention
+        Entity::delete_many()
+            .filter(Column::CreatedAt.lt(now - Duration::seconds(rule.retention)))
+            .exec(db)
+            .await?;
+        let kept: Option<i32> = Entity::find()
+            .select_only()
+            .column(Column::Id)
+            .filter(Column::Pos.eq(sender.pos))
+            .order_by_desc(Column::Id)
+            .offset(rule.max_per_node)
+            .into_tuple()
+            .one(db)
+            .await?;
+        if let Some(id) = kept {
+            Entity::delete_many()
+                .filter(Column::Pos.eq(sender.pos))
+                .filter(Column::Id.lte(id))
+                .exec(db)
+                .await?;
+        }
+        Ok(m)
+    }
+
+    /// Messages reaching the node posted after the message `after`, oldest first.
+    pub async fn list_reaching<C: ConnectionTrait>(
+        db: &C,
+        node: NodeID,
+        after: i32,
+    ) -> Result<Vec<Model>, OperationError> {
+        let rule = &config::rule().chat;
+        let reach = world::around(node, rule.max_radius);
+        let ms = Entity::find()
+            .filter(Region::any_condition(&reach, Column::Pos))
+            .filter(Column::Id.gt(after))
+            .filter(Column::CreatedAt.gte(Utc::now() - Duration::seconds(rule.retention)))
+            .order_by_asc(Column::Id)
+            .all(db)
+            .await?;
+        Ok(ms
+            .into_iter()
+            .filter(|m| world::distance(NodeID::from_i32(m.pos), node) <= m.radius as i32)
+            .collect())
+    }
+}
@@ -1,23 +1,138 @@
 use sea_orm::{
     ActiveModelTrait, ColumnTrait, Condition, ConnectionTrait, DatabaseTransaction, EntityTrait,
-    QueryFilter, Set,
+    JoinType, ModelTrait, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, RelationTrait, Set,
 };
 use variant::PublicPlayer;
 
-use crate::err::{ModelError, OperationError};
+use crate::{
+    config,
+    err::{ModelError, OperationError},
+    world,
+};
 use entropy_base::grid::NodeID;
 
+pub mod achievement;
+pub mod alliance;
+pub mod battery;
+pub mod claim;
+pub mod engine;
+pub mod event;
 pub mod guest;
+pub mod invite;
+pub mod leaderboard;
+pub mod message;
 pub mod node;
+pub mod offer;
+pub mod permit;
 pub mod player;
 pub mod prelude;
+pub mod region;
+pub mod season;
+pub mod siphon;
+pub mod spawn;
 pub mod variant;
+pub mod visit;
 
 pub async fn get_node(
     txn: &DatabaseTransaction,
     node_id: NodeID,
 ) -> Result<node::Model, OperationError> {
-    Ok(node::Model::get_or_init(txn, node_id.into()).await?)
+    let n = node::Model::get_or_init(txn, node_id).await?;
+    engine::Model::run_on_node(txn, n).await
+}
+
+/// Whether the player has visited the node, or has a guest within sight of it.
+pub async fn in_sight<C: ConnectionTrait>(
+    db: &C,
+    player_id: i32,
+    node_id: NodeID,
+) -> Result<bool, OperationError> {
+    if visit::Entity::find_by_id((player_id, node_id.into_i32()))
+        .one(db)
+        .await?
+        .is_some()
+    {
+        return Ok(true);
+    }
+    let sight = world::around(node_id, config::rule().fog.sight);
+    Ok(guest::Entity::find()
+        .filter(guest::Column::MasterId.eq(player_id))
+        .filter(region::Region::any_condition(&sight, guest::Column::Pos))
+        .count(db)
+        .await?
+        > 0)
+}
+
+/// Node seen by the player, or anyone if `player` is None.
+///
+/// With fog enabled, nodes out of sight are summarized and never generated.
+pub async fn view_node(
+    txn: &DatabaseTransaction,
+    player: Option<(i32, String)>,
+    node_id: NodeID,
+) -> Result<variant::NodeView, OperationError> {
+    let node_id = world::locate(node_id)?;
+    match get_visible_node(txn, player, node_id).await {
+        Ok(n) => Ok(variant::NodeView::Full(variant::TerrainNode {
+            terrain: n.terrain,
+            node: n.into(),
+        })),
+        Err(OperationError::NodeInFog(_)) => {
+            let n = node::Entity::find_by_id(node_id.into_i32())
+                .one(txn)
+                .await?;
+            Ok(variant::NodeView::Summary(variant::NodeSummary {
+                id: node_id,
+                fog: true,
+                temperature: n
+                    .as_ref()
+                    .and_then(|n| n.mean_temperature())
+                    .map(|t| t.round() as i16),
+                terrain: n.map(|n| n.terrain),
+            }))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Node with full data, if the player is able to see it.
+///
+/// Viewing never generates a node, a node not generated yet is previewed instead.
+pub async fn get_visible_node(
+    txn: &DatabaseTransaction,
+    player: Option<(i32, String)>,
+    node_id: NodeID,
+) -> Result<node::Model, OperationError> {
+    let node_id = world::locate(node_id)?;
+    if config::rule().fog.enable {
+        let Some((id, password)) = player else {
+            return Err(OperationError::NodeInFog(node_id));
+        };
+        let p = get_exact_player(txn, id, password).await?;
+        if !in_sight(txn, p.id, node_id).await? {
+            return Err(OperationError::NodeInFog(node_id));
+        }
+    }
+    if node::Entity::find_by_id(node_id.into_i32())
+        .count(txn)
+        .await?
+        == 0
+    {
+        return Ok(node::Model::preview(node_id));
+    }
+    get_node(txn, node_id).await
+}
+
+pub async fn list_visit(
+    txn: &DatabaseTransaction,
+    id: i32,
+    password: String,
+) -> Result<Vec<visit::Model>, OperationError> {
+    let p = get_exact_player(txn, id, password).await?;
+    Ok(p.find_related(visit::Entity)
+        .order_by_asc(visit::Column::VisitedAt)
+        .all(txn)
+        .await?)
 }
 
 pub async fn register_player<C: ConnectionTrait>(
@@ -73,6 +188,18 @@ pub async fn get_exact_player<C: ConnectionTrait>(
     }
 }
 
+pub async fn get_player_profile<C: ConnectionTrait>(
+    db: &C,
+    id: i32,
+) -> Result<variant::PlayerProfile, OperationError> {
+    let player = get_exact_player_public(db, id).await?;
+    let achievements = achievement::list(db, id).await?;
+    Ok(variant::PlayerProfile {
+        player,
+        achievements,
+    })
+}
+
 pub async fn get_exact_player_public<C: ConnectionTrait>(
     db: &C,
     id: i32,
@@ -93,11 +220,11 @@ pub async fn list_guest(
     id: i32,
     password: String,
 ) -> Result<Vec<guest::Model>, OperationError> {
-    Ok(get_player(txn, id, password)
+    get_player(txn, id, password)
         .await?
         .ok_or(OperationError::PlayerNotExist(id))?
         .list_guest(txn)
-        .await?)
+        .await
 }
 
 pub async fn spawn_guest(
@@ -107,6 +234,7 @@ pub async fn spawn_guest(
 ) -> Result<guest::Model, OperationError> {
     let p = get_exact_player(txn, id, password).await?;
     let rnt = p.spawn_guest(txn).await?;
+    visit::record(txn, id, rnt.pos).await?;
     Ok(rnt)
 }
 
@@ -136,6 +264,13 @@ pub async fn walk(
 
     // move guest, more easily rollback than node change
     let g_next = g.walk_free(txn, to).await?;
+    visit::record(txn, id, g_next.pos).await?;
+    achievement::evaluate(
+        txn,
+        id,
+        achievement::Event::Walk(NodeID::from_i32(g_next.pos)),
+    )
+    .await?;
 
     // exhaust wasted heat
     let n = get_node(txn, NodeID::from_i32(g.pos)).await?; // use old guest position
@@ -156,12 +291,29 @@ pub async fn harvest(
         .get_guest(txn, gid)
         .await?;
 
-    let n = get_node(&txn, NodeID::from_i32(g.pos)).await?;
+    let node_id = NodeID::from_i32(g.pos);
+    let claim = check_claim(txn, node_id, id).await?;
+
+    let n = get_node(txn, node_id).await?;
+    let reserve = g.energy;
+    let terrain = n.terrain;
     let (g, n) = g
-        ._harvest_active_model(n.into(), at)
-        .map_err(|e| OperationError::Model(e))?;
+        ._harvest_active_model(n.into(), terrain, at)
+        .map_err(OperationError::Model)?;
     let g = g.update(txn).await?;
-    n.update(txn).await?;
+    let n = n.update(txn).await?;
+    achievement::evaluate(
+        txn,
+        id,
+        achievement::Event::Harvest {
+            energy: g.energy - reserve,
+            cell: n.data.get(at).map_or(0, |&c| c as i8),
+        },
+    )
+    .await?;
+
+    // pay tax on the energy harvested
+    let g = pay_tax(txn, claim, g, claim::get_tax(g.energy - reserve)).await?;
     Ok(g)
 }
 
@@ -179,13 +331,15 @@ pub async fn arrange(
     let g_count = p.count_guest(txn).await?;
     let g_count = g_count.try_into().map_err(|_| {
         OperationError::Model(ModelError::OutOfLimit {
-            desc: format!("owned guest number"),
+            desc: "owned guest number".to_string(),
             limit_type: "u32",
         })
     })?;
     let consume_energy = 2i64.pow(g_count);
     let g = g.consume_energy(txn, consume_energy).await?;
     let new_g = g.arrange_free(txn, transfer_energy).await?;
+    let guests = p.count_guest(txn).await?;
+    achievement::evaluate(txn, id, achievement::Event::Arrange { guests }).await?;
 
     Ok(new_g)
 }
@@ -214,8 +368,728 @@ pub async fn heat(
         .await?
         .get_guest(txn, gid)
         .await?;
-    let n = get_node(txn, NodeID::from_i32(g.pos)).await?;
+    let node_id = NodeID::from_i32(g.pos);
+    let claim = check_claim(txn, node_id, id).await?;
+
+    let n = get_node(txn, node_id).await?;
     n._heat(txn, at, energy).await?;
     let g = g.consume_energy(txn, energy).await?;
+
+    // pay tax on the energy spent
+    let g = pay_tax(txn, claim, g, claim::get_tax(energy)).await?;
+    Ok(g)
+}
+
+pub async fn cool(
+    txn: &DatabaseTransaction,
+    id: i32,
+    password: String,
+    gid: i32,
+    at: usize,
+    energy: i64,
+) -> Result<guest::Model, OperationError> {
+    let g = get_exact_player(txn, id, password)
+        .await?
+        .get_guest(txn, gid)
+        .await?;
+    let node_id = NodeID::from_i32(g.pos);
+    let claim = check_claim(txn, node_id, id).await?;
+
+    let n = get_node(txn, node_id).await?;
+    let reserve = g.energy;
+    let (g, n) = g._cool_active_model(n, at, energy)?;
+    let g = g.update(txn).await?;
+    let n = n.update(txn).await?;
+    let cell = n.data.get(at).map_or(0, |&c| c as i8);
+    achievement::evaluate(txn, id, achievement::Event::Cool { cell }).await?;
+
+    // pay tax on the energy spent
+    let spent = reserve - g.energy;
+    let g = pay_tax(txn, claim, g, claim::get_tax(spent)).await?;
     Ok(g)
 }
+
+pub async fn build_engine(
+    txn: &DatabaseTransaction,
+    id: i32,
+    password: String,
+    gid: i32,
+    cell: usize,
+    other: Option<usize>,
+) -> Result<engine::Model, OperationError> {
+    let g = get_exact_player(txn, id, password)
+        .await?
+        .get_guest(txn, gid)
+        .await?;
+    let node_id = NodeID::from_i32(g.pos);
+    // engines built on a taxed node pay the tax on what they harvest
+    check_claim(txn, node_id, id).await?;
+    g.consume_energy(txn, config::rule().engine.build_cost)
+        .await?;
+    let n = get_node(txn, node_id).await?;
+    let e = engine::Model::build(txn, &n, id, cell, other).await?;
+    Ok(e)
+}
+
+pub async fn list_engine(
+    txn: &DatabaseTransaction,
+    id: i32,
+    password: String,
+) -> Result<Vec<engine::Model>, OperationError> {
+    let p = get_exact_player(txn, id, password).await?;
+    let pos: Vec<i32> = p
+        .find_related(engine::Entity)
+        .select_only()
+        .column(engine::Column::Pos)
+        .distinct()
+        .into_tuple()
+        .all(txn)
+        .await?;
+    // run the engines before showing them
+    for pos in pos {
+        get_node(txn, NodeID::from_i32(pos)).await?;
+    }
+    Ok(p.find_related(engine::Entity)
+        .order_by_asc(engine::Column::Id)
+        .all(txn)
+        .await?)
+}
+
+pub async fn collect_engine(
+    txn: &DatabaseTransaction,
+    id: i32,
+    password: String,
+    gid: i32,
+    eid: i32,
+) -> Result<guest::Model, OperationError> {
+    let g = get_exact_player(txn, id, password)
+        .await?
+        .get_guest(txn, gid)
+        .await?;
+    let e = engine::Entity::find_by_id(eid)
+        .one(txn)
+        .await?
+        .filter(|e| e.master_id == id)
+        .ok_or(OperationError::EngineNotExist(eid))?;
+    if e.pos != g.pos {
+        return Err(OperationError::NotOnSameNode {
+            here: NodeID::from_i32(g.pos),
+            there: NodeID::from_i32(e.pos),
+        });
+    }
+
+    // run the engine before collecting
+    get_node(txn, NodeID::from_i32(e.pos)).await?;
+    let e = engine::Entity::find_by_id(eid)
+        .one(txn)
+        .await?
+        .ok_or(OperationError::EngineNotExist(eid))?;
+    let (_e, energy) = e.collect(txn).await?;
+    g.gain_energy(txn, energy).await
+}
+
+pub async fn build_battery(
+    txn: &DatabaseTransaction,
+    id: i32,
+    password: String,
+    gid: i32,
+    capacity: i64,
+    access: battery::Access,
+) -> Result<battery::Model, OperationError> {
+    let max = config::rule().battery.max_capacity;
+    if !(1..=max).contains(&capacity) {
+        return Err(OperationError::Model(ModelError::OutOfLimit {
+            desc: format!("battery capacity {capacity}, max {max}"),
+            limit_type: "capacity",
+        }));
+    }
+    let g = get_exact_player(txn, id, password)
+        .await?
+        .get_guest(txn, gid)
+        .await?;
+    let g = g
+        .consume_energy(txn, battery::get_build_cost(capacity))
+        .await?;
+    battery::Model::build(txn, g.pos, id, capacity, access).await
+}
+
+pub async fn list_battery(
+    txn: &DatabaseTransaction,
+    id: i32,
+    password: String,
+    gid: i32,
+) -> Result<Vec<battery::Model>, OperationError> {
+    let g = get_exact_player(txn, id, password)
+        .await?
+        .get_guest(txn, gid)
+        .await?;
+    let bs = battery::Entity::find()
+        .filter(battery::Column::Pos.eq(g.pos))
+        .order_by_asc(battery::Column::Id)
+        .all(txn)
+        .await?;
+    let mut rnt = Vec::with_capacity(bs.len());
+    for b in bs {
+        rnt.push(b.leak(txn).await?);
+    }
+    Ok(rnt)
+}
+
+/// Get a battery the guest is able to use.
+async fn get_usable_battery(
+    txn: &DatabaseTransaction,
+    g: &guest::Model,
+    bid: i32,
+) -> Result<battery::Model, OperationError> {
+    let b = battery::Entity::find_by_id(bid)
+        .one(txn)
+        .await?
+        .ok_or(OperationError::BatteryNotExist(bid))?;
+    if b.pos != g.pos {
+        return Err(OperationError::NotOnSameNode {
+            here: NodeID::from_i32(g.pos),
+            there: NodeID::from_i32(b.pos),
+        });
+    }
+    if !b.accessible_by(txn, g.master_id).await? {
+        return Err(OperationError::BatteryAccessDenied(bid));
+    }
+    Ok(b)
+}
+
+pub async fn deposit_battery(
+    txn: &DatabaseTransaction,
+    id: i32,
+    password: String,
+    gid: i32,
+    bid: i32,
+    energy: i64,
+) -> Result<battery::Model, OperationError> {
+    if energy <= 0 {
+        return Err(OperationError::NonPositiveEnergy(energy));
+    }
+    let g = get_exact_player(txn, id, password)
+        .await?
+        .get_guest(txn, gid)
+        .await?;
+    let b = get_usable_battery(txn, &g, bid).await?;
+    g.consume_energy(txn, energy).await?;
+    b.deposit(txn, energy).await
+}
+
+pub async fn withdraw_battery(
+    txn: &DatabaseTransaction,
+    id: i32,
+    password: String,
+    gid: i32,
+    bid: i32,
+    energy: i64,
+) -> Result<guest::Model, OperationError> {
+    if energy <= 0 {
+        return Err(OperationError::NonPositiveEnergy(energy));
+    }
+    let g = get_exact_player(txn, id, password)
+        .await?
+        .get_guest(txn, gid)
+        .await?;
+    let b = get_usable_battery(txn, &g, bid).await?;
+    b.withdraw(txn, energy).await?;
+    g.gain_energy(txn, energy).await
+}
+
+pub async fn siphon(
+    txn: &DatabaseTransaction,
+    id: i32,
+    password: String,
+    gid: i32,
+    target: i32,
+) -> Result<siphon::Model, OperationError> {
+    let g = get_exact_player(txn, id, password)
+        .await?
+        .get_guest(txn, gid)
+        .await?;
+    let t = guest::Entity::find_by_id(target)
+        .one(txn)
+        .await?
+        .ok_or(OperationError::GuestNotExist(target))?;
+    if t.master_id == id {
+        return Err(OperationError::TargetNotForeign(target));
+    }
+    if alliance::allied(txn, id, t.master_id).await? {
+        return Err(OperationError::TargetAllied(target));
+    }
+    // a guest for sale is held in escrow, its energy goes to the buyer
+    if offer::locked(txn, target).await? {
+        return Err(OperationError::GuestLocked(target));
+    }
+    if t.pos != g.pos {
+        return Err(OperationError::NotOnSameNode {
+            here: NodeID::from_i32(g.pos),
+            there: NodeID::from_i32(t.pos),
+        });
+    }
+    let t = t.equalize(txn).await?;
+    let (record, _g, _t) = siphon::Model::attempt(txn, g, t).await?;
+    let event = achievement::Event::Siphon {
+        success: record.success,
+    };
+    achievement::evaluate(txn, id, event).await?;
+    Ok(record)
+}
+
+pub async fn list_siphon(
+    txn: &DatabaseTransaction,
+    id: i32,
+    password: String,
+) -> Result<Vec<siphon::Model>, OperationError> {
+    let p = get_exact_player(txn, id, password).await?;
+    siphon::Model::list_recent(txn, p.id, 100).await
+}
+
+/// Check the claim before using the node.
+///
+/// Return the claim if the player should pay tax to it.
+async fn check_claim(
+    txn: &DatabaseTransaction,
+    node: NodeID,
+    player_id: i32,
+) -> Result<Option<claim::Model>, OperationError> {
+    match claim::Model::levy(txn, node, player_id).await? {
+        claim::Levy::Free => Ok(None),
+        claim::Levy::Taxed(c) => Ok(Some(c)),
+        claim::Levy::Denied => Err(OperationError::NodeClaimed(node)),
+    }
+}
+
+async fn pay_tax(
+    txn: &DatabaseTransaction,
+    claim: Option<claim::Model>,
+    g: guest::Model,
+    tax: i64,
+) -> Result<guest::Model, OperationError> {
+    match claim {
+        Some(c) if tax > 0 => {
+            let g = g.consume_energy(txn, tax).await?;
+            c.pay_tax(txn, tax).await?;
+            Ok(g)
+        }
+        _ => Ok(g),
+    }
+}
+
+pub async fn claim_node(
+    txn: &DatabaseTransaction,
+    id: i32,
+    password: String,
+    gid: i32,
+    mode: claim::Mode,
+    duration: i64,
+    stake: Option<i64>,
+) -> Result<claim::Model, OperationError> {
+    let max = config::rule().claim.max_duration;
+    if !(1..=max).contains(&duration) {
+        return Err(OperationError::Model(ModelError::OutOfLimit {
+            desc: format!("claim duration {duration}, max {max}"),
+            limit_type: "second",
+        }));
+    }
+    let g = get_exact_player(txn, id, password)
+        .await?
+        .get_guest(txn, gid)
+        .await?;
+    let cost = claim::get_claim_cost(duration);
+    let stake = stake.unwrap_or(cost);
+    if stake < cost {
+        return Err(OperationError::ClaimStakeTooLow {
+            require: cost,
+            offer: stake,
+        });
+    }
+    g.verify_energy(stake)?;
+    let (c, paid) =
+        claim::Model::claim(txn, NodeID::from_i32(g.pos), id, mode, duration, stake).await?;
+    g.consume_energy(txn, paid).await?;
+    achievement::evaluate(txn, id, achievement::Event::Claim).await?;
+    Ok(c)
+}
+
+pub async fn collect_claim(
+    txn: &DatabaseTransaction,
+    id: i32,
+    password: String,
+    gid: i32,
+) -> Result<guest::Model, OperationError> {
+    let g = get_exact_player(txn, id, password)
+        .await?
+        .get_guest(txn, gid)
+        .await?;
+    let node_id = NodeID::from_i32(g.pos);
+    let c = claim::Entity::find_by_id(g.pos)
+        .one(txn)
+        .await?
+        .filter(|c| c.master_id == id)
+        .ok_or(OperationError::ClaimNotExist(node_id))?;
+    let (_c, energy) = c.collect(txn).await?;
+    g.gain_energy(txn, energy).await
+}
+
+pub async fn list_claim<C: ConnectionTrait>(
+    db: &C,
+    region: region::Region,
+) -> Result<Vec<claim::Model>, OperationError> {
+    world::check_region(region)?;
+    claim::Model::list_in_region(db, region).await
+}
+
+pub async fn create_alliance(
+    txn: &DatabaseTransaction,
+    id: i32,
+    password: String,
+    name: String,
+) -> Result<alliance::Model, OperationError> {
+    let p = get_exact_player(txn, id, password).await?;
+    alliance::Model::create(txn, p, name).await
+}
+
+pub async fn get_alliance_public<C: ConnectionTrait>(
+    db: &C,
+    aid: i32,
+) -> Result<variant::PublicAlliance, OperationError> {
+    let a = alliance::Entity::find_by_id(aid)
+        .one(db)
+        .await?
+        .ok_or(OperationError::AllianceNotExist(aid))?;
+    let members = a.members(db).await?;
+    Ok(variant::PublicAlliance {
+        alliance: a,
+        members,
+    })
+}
+
+/// Alliance of the player.
+async fn get_own_alliance(
+    txn: &DatabaseTransaction,
+    p: &player::Model,
+) -> Result<alliance::Model, OperationError> {
+    let aid = p.alliance_id.ok_or(OperationError::NotInAlliance)?;
+    alliance::Entity::find_by_id(aid)
+        .one(txn)
+        .await?
+        .ok_or(OperationError::AllianceNotExist(aid))
+}
+
+/// Alliance led by the player.
+async fn get_led_alliance(
+    txn: &DatabaseTransaction,
+    p: &player::Model,
+) -> Result<alliance::Model, OperationError> {
+    let a = get_own_alliance(txn, p).await?;
+    if a.leader_id != p.id {
+        return Err(OperationError::NotAllianceLeader(a.leader_id));
+    }
+    Ok(a)
+}
+
+pub async fn invite_alliance(
+    txn: &DatabaseTransaction,
+    id: i32,
+    password: String,
+    player_id: i32,
+) -> Result<invite::Model, OperationError> {
+    let p = get_exact_player(txn, id, password).await?;
+    let a = get_led_alliance(txn, &p).await?;
+    a.invite(txn, player_id).await
+}
+
+pub async fn list_invite(
+    txn: &DatabaseTransaction,
+    id: i32,
+    password: String,
+) -> Result<Vec<invite::Model>, OperationError> {
+    let p = get_exact_player(txn, id, password).await?;
+    Ok(p.find_related(invite::Entity)
+        .order_by_asc(invite::Column::Id)
+        .all(txn)
+        .await?)
+}
+
+pub async fn accept_alliance(
+    txn: &DatabaseTransaction,
+    id: i32,
+    password: String,
+    aid: i32,
+) -> Result<player::Model, OperationError> {
+    let p = get_exact_player(txn, id, password).await?;
+    let a = alliance::Entity::find_by_id(aid)
+        .one(txn)
+        .await?
+        .ok_or(OperationError::AllianceNotExist(aid))?;
+    a.accept(txn, p).await
+}
+
+pub async fn leave_alliance(
+    txn: &DatabaseTransaction,
+    id: i32,
+    password: String,
+) -> Result<player::Model, OperationError> {
+    let p = get_exact_player(txn, id, password).await?;
+    let a = get_own_alliance(txn, &p).await?;
+    a.remove(txn, p).await
+}
+
+pub async fn kick_alliance(
+    txn: &DatabaseTransaction,
+    id: i32,
+    password: String,
+    player_id: i32,
+) -> Result<PublicPlayer, OperationError> {
+    let p = get_exact_player(txn, id, password).await?;
+    let a = get_led_alliance(txn, &p).await?;
+    if player_id == id {
+        return Err(OperationError::NotAllianceMember(player_id));
+    }
+    let m = player::Entity::find_by_id(player_id)
+        .one(txn)
+        .await?
+        .ok_or(OperationError::NotAllianceMember(player_id))?;
+    a.remove(txn, m).await?;
+    get_exact_player_public(txn, player_id).await
+}
+
+/// Guests of the other members in the player's alliance.
+pub async fn list_ally_guest(
+    txn: &DatabaseTransaction,
+    id: i32,
+    password: String,
+) -> Result<Vec<variant::DetectedGuest>, OperationError> {
+    let p = get_exact_player(txn, id, password).await?;
+    let aid = p.alliance_id.ok_or(OperationError::NotInAlliance)?;
+    Ok(guest::Entity::find()
+        .join(JoinType::InnerJoin, guest::Relation::Player.def())
+        .filter(player::Column::AllianceId.eq(aid))
+        .filter(guest::Column::MasterId.ne(id))
+        .order_by_asc(guest::Column::Id)
+        .into_partial_model::<variant::DetectedGuest>()
+        .all(txn)
+        .await?)
+}
+
+/// Give energy to a guest of the player or an ally on the same node.
+pub async fn give_energy(
+    txn: &DatabaseTransaction,
+    id: i32,
+    password: String,
+    gid: i32,
+    target: i32,
+    energy: i64,
+) -> Result<guest::Model, OperationError> {
+    if !config::rule().alliance.give {
+        return Err(OperationError::GiveDisabled);
+    }
+    if energy <= 0 {
+        return Err(OperationError::NonPositiveEnergy(energy));
+    }
+    let g = get_exact_player(txn, id, password)
+        .await?
+        .get_guest(txn, gid)
+        .await?;
+    let t = guest::Entity::find_by_id(target)
+        .one(txn)
+        .await?
+        .ok_or(OperationError::GuestNotExist(target))?;
+    if t.id == g.id {
+        return Err(OperationError::TargetNotAllied(target));
+    }
+    if t.master_id != id && !alliance::allied(txn, id, t.master_id).await? {
+        return Err(OperationError::TargetNotAllied(target));
+    }
+    if t.pos != g.pos {
+        return Err(OperationError::NotOnSameNode {
+            here: NodeID::from_i32(g.pos),
+            there: NodeID::from_i32(t.pos),
+        });
+    }
+    let g = g.consume_energy(txn, energy).await?;
+    t.gain_energy(txn, energy).await?;
+    Ok(g)
+}
+
+pub async fn post_message(
+    txn: &DatabaseTransaction,
+    id: i32,
+    password: String,
+    gid: i32,
+    content: String,
+    radius: i16,
+) -> Result<message::Model, OperationError> {
+    let rule = &config::rule().chat;
+    let len = content.chars().count();
+    if !(1..=rule.max_length).contains(&len) {
+        return Err(OperationError::Model(ModelError::OutOfLimit {
+            desc: format!("message length {len}, max {}", rule.max_length),
+            limit_type: "character",
+        }));
+    }
+    if !(0..=rule.max_radius).contains(&radius) {
+        return Err(OperationError::Model(ModelError::OutOfLimit {
+            desc: format!("broadcast radius {radius}, max {}", rule.max_radius),
+            limit_type: "node",
+        }));
+    }
+    let g = get_exact_player(txn, id, password)
+        .await?
+        .get_guest(txn, gid)
+        .await?;
+    let g = g
+        .consume_energy(txn, message::get_broadcast_cost(radius))
+        .await?;
+    message::Model::post(txn, &g, content, radius).await
+}
+
+pub async fn list_message(
+    txn: &DatabaseTransaction,
+    id: i32,
+    password: String,
+    gid: i32,
+    after: i32,
+) -> Result<Vec<message::Model>, OperationError> {
+    let g = get_exact_player(txn, id, password)
+        .await?
+        .get_guest(txn, gid)
+        .await?;
+    message::Model::list_reaching(txn, NodeID::from_i32(g.pos), after).await
+}
+
+pub async fn post_offer(
+    txn: &DatabaseTransaction,
+    id: i32,
+    password: String,
+    gid: i32,
+    goods: offer::Goods,
+    price: i64,
+    expire: i64,
+) -> Result<offer::Model, OperationError> {
+    let rule = &config::rule().market;
+    // a price of 0 gives the goods away
+    if price < 0 {
+        return Err(OperationError::NegativePrice(price));
+    }
+    if !(1..=rule.max_expire).contains(&expire) {
+        return Err(OperationError::Model(ModelError::OutOfLimit {
+            desc: format!("offer expire {expire}, max {}", rule.max_expire),
+            limit_type: "second",
+        }));
+    }
+    match goods {
+        offer::Goods::Energy { amount } if amount <= 0 => {
+            return Err(OperationError::NonPositiveEnergy(amount));
+        }
+        offer::Goods::Access { duration, .. }
+            if !(1..=rule.max_access_duration).contains(&duration) =>
+        {
+            return Err(OperationError::Model(ModelError::OutOfLimit {
+                desc: format!(
+                    "access duration {duration}, max {}",
+                    rule.max_access_duration
+                ),
+                limit_type: "second",
+            }));
+        }
+        _ => {}
+    }
+
+    let g = get_exact_player(txn, id, password)
+        .await?
+        .get_guest(txn, gid)
+        .await?;
+    offer::expire_due(txn).await?;
+    if offer::Model::count_open(txn, id).await? >= rule.max_open {
+        return Err(OperationError::OfferLimit { max: rule.max_open });
+    }
+    offer::Model::post(txn, g, goods, price, expire).await
+}
+
+pub async fn accept_offer(
+    txn: &DatabaseTransaction,
+    id: i32,
+    password: String,
+    gid: i32,
+    oid: i32,
+) -> Result<offer::Model, OperationError> {
+    let p = get_exact_player(txn, id, password).await?;
+    let g = p.get_guest(txn, gid).await?;
+    offer::expire_due(txn).await?;
+    let o = offer::Entity::find_by_id(oid)
+        .one(txn)
+        .await?
+        .ok_or(OperationError::OfferNotExist(oid))?;
+    let o = o.accept(txn, g).await?;
+    if o.kind == offer::Kind::Guest {
+        let guests = p.count_guest(txn).await?;
+        achievement::evaluate(txn, id, achievement::Event::Arrange { guests }).await?;
+    }
+    Ok(o)
+}
+
+pub async fn cancel_offer(
+    txn: &DatabaseTransaction,
+    id: i32,
+    password: String,
+    oid: i32,
+) -> Result<offer::Model, OperationError> {
+    let p = get_exact_player(txn, id, password).await?;
+    offer::expire_due(txn).await?;
+    let o = p
+        .find_related(offer::Entity)
+        .filter(offer::Column::Id.eq(oid))
+        .one(txn)
+        .await?
+        .ok_or(OperationError::OfferNotExist(oid))?;
+    if !o.is_open() {
+        return Err(OperationError::OfferNotOpen(oid));
+    }
+    o.close(txn, offer::Status::Cancelled).await
+}
+
+pub async fn list_offer(
+    txn: &DatabaseTransaction,
+    kind: Option<offer::Kind>,
+) -> Result<Vec<offer::Model>, OperationError> {
+    offer::expire_due(txn).await?;
+    offer::Model::list_open(txn, kind, 100).await
+}
+
+pub async fn list_own_offer(
+    txn: &DatabaseTransaction,
+    id: i32,
+    password: String,
+) -> Result<Vec<offer::Model>, OperationError> {
+    let p = get_exact_player(txn, id, password).await?;
+    offer::expire_due(txn).await?;
+    offer::Model::list_involving(txn, p.id, 100).await
+}
+
+pub async fn list_leaderboard<C: ConnectionTrait>(
+    db: &C,
+    by: leaderboard::Rank,
+    page: u64,
+    size: u64,
+) -> Result<Vec<variant::Ranked>, OperationError> {
+    if !(1..=100).contains(&size) {
+        return Err(OperationError::Model(ModelError::OutOfLimit {
+            desc: format!("page size {size}, max 100"),
+            limit_type: "entry",
+        }));
+    }
+    leaderboard::page(db, by, page, size).await
+}
+
+pub async fn get_season<C: ConnectionTrait>(db: &C) -> Result<season::Model, OperationError> {
+    season::Model::current(db)
+        .await?
+        .ok_or(OperationError::SeasonNotStarted)
+}
+
+pub async fn list_event<C: ConnectionTrait>(db: &C) -> Result<Vec<event::Model>, OperationError> {
+    Ok(event::list(db, 20).await?)
+}
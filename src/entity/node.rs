@@ -1,11 +1,20 @@
-use rand::{rngs::SmallRng, seq::IteratorRandom, SeedableRng};
-use sea_orm::{entity::prelude::*, sea_query::OnConflict, DatabaseTransaction, Set};
+use chrono::Utc;
+use rand::{rngs::SmallRng, seq::IteratorRandom, Rng, SeedableRng};
+use sea_orm::{
+    entity::prelude::*, sea_query::OnConflict, DatabaseTransaction, QueryOrder, QuerySelect, Set,
+};
 use serde::{Deserialize, Serialize};
 use tracing::instrument;
 
-use crate::err::{ModelError, OperationError, RuntimeError};
+use crate::{
+    config,
+    err::{ModelError, OperationError, RuntimeError},
+    world,
+};
 use entropy_base::grid::{FlatID, Node, NodeData, NodeID};
 
+use super::region::Region;
+
 #[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
 #[sea_orm(table_name = "node")]
 pub struct Model {
@@ -17,12 +26,87 @@ pub struct Model {
     pub id: i32,
     #[serde(with = "serde_bytes")]
     pub data: Vec<u8>,
+    #[sea_orm(default_value = "normal")]
+    pub terrain: Terrain,
+    /// when the cells last drifted back toward the generated values or were changed
+    #[sea_orm(default_expr = "Expr::current_timestamp()")]
+    pub touched_at: DateTimeUtc,
+    /// written by a map import, which only the `map` generator knows how to regenerate
+    #[sea_orm(default_value = false)]
+    pub imported: bool,
+}
+
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize,
+)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+#[serde(rename_all = "snake_case")]
+pub enum Terrain {
+    #[default]
+    #[sea_orm(string_value = "normal")]
+    Normal,
+    /// impassable
+    #[sea_orm(string_value = "wall")]
+    Wall,
+    /// harder to walk into, guest temperature settles slower
+    #[sea_orm(string_value = "insulator")]
+    Insulator,
+    /// guest temperature settles faster
+    #[sea_orm(string_value = "conductor")]
+    Conductor,
+    /// harder to walk into, harvested cells are partly recharged
+    #[sea_orm(string_value = "vent")]
+    Vent,
+}
+
+impl Terrain {
+    /// Extra energy to walk into, None if impassable.
+    pub fn walk_cost(self) -> Option<i64> {
+        let rule = &config::rule().terrain;
+        match self {
+            Terrain::Wall => None,
+            Terrain::Insulator => Some(rule.insulator_walk_cost),
+            Terrain::Vent => Some(rule.vent_walk_cost),
+            Terrain::Normal | Terrain::Conductor => Some(0),
+        }
+    }
+
+    /// Factor of `rule.thermal.half_life` for guests on the terrain.
+    pub fn thermal_factor(self) -> f32 {
+        let rule = &config::rule().terrain;
+        match self {
+            Terrain::Insulator => rule.insulator_thermal_factor,
+            Terrain::Conductor => 1.0 / rule.conductor_thermal_factor,
+            _ => 1.0,
+        }
+    }
+
+    /// Set the cell harvested from `before` to `after`, vents recharge part of the change
+    /// at once by drawing the heat from the other cells, as much as they can give.
+    pub fn recharge(self, data: &mut [u8], cell: usize, before: i8, after: i8) {
+        data[cell] = after as u8;
+        if self != Terrain::Vent {
+            return;
+        }
+        let restored =
+            ((before as f32 - after as f32) * config::rule().terrain.vent_recharge).round() as i16;
+        let left = spread(data, cell, -restored);
+        data[cell] = (after as i16 + restored + left) as i8 as u8;
+    }
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
     #[sea_orm(has_many = "super::guest::Entity")]
     Guest,
+    #[sea_orm(has_many = "super::engine::Entity")]
+    Engine,
+    #[sea_orm(has_many = "super::battery::Entity")]
+    Battery,
+    #[sea_orm(has_many = "super::claim::Entity")]
+    Claim,
+    #[sea_orm(has_many = "super::message::Entity")]
+    Message,
 }
 
 impl Related<super::guest::Entity> for Entity {
@@ -31,25 +115,135 @@ impl Related<super::guest::Entity> for Entity {
     }
 }
 
+impl Related<super::engine::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Engine.def()
+    }
+}
+
+impl Related<super::battery::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Battery.def()
+    }
+}
+
+impl Related<super::claim::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Claim.def()
+    }
+}
+
+impl Related<super::message::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Message.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
 
 impl Model {
+    /// A new node from the world generator.
+    pub fn generate(id: NodeID) -> ActiveModel {
+        ActiveModel {
+            id: Set(id.into_i32()),
+            data: Set(world::generate(id).into()),
+            terrain: Set(world::terrain(id)),
+            touched_at: Set(Utc::now()),
+            imported: Set(false),
+        }
+    }
+
+    /// The node as it would be generated, not inserted, for viewing a node nobody reached yet.
+    pub fn preview(id: NodeID) -> Model {
+        Model {
+            id: id.into_i32(),
+            data: world::generate(id).into(),
+            terrain: world::terrain(id),
+            touched_at: Utc::now(),
+            imported: false,
+        }
+    }
+
     // this method MAY use quary multiple times, so transaction is required
     pub async fn get_or_init(
         txn: &DatabaseTransaction,
         id: NodeID,
     ) -> Result<Model, OperationError> {
+        let id = world::locate(id)?;
         if let Some(node) = Entity::find_by_id(id.into_i32()).one(txn).await? {
-            Ok(node)
+            Ok(node.regenerate(txn).await?)
         } else {
-            let n = ActiveModel {
-                id: Set(id.into_i32()),
-                data: Set(NodeData::random().into()),
-            };
-            Ok(n.insert(txn).await?)
+            Ok(Self::generate(id).insert(txn).await?)
         }
     }
 
+    /// Drift the cells back toward the values the node is generated with,
+    /// the difference halves every `rule.regen.half_life` seconds.
+    pub async fn regenerate<C: ConnectionTrait>(self, db: &C) -> Result<Model, DbErr> {
+        let now = Utc::now();
+        let Some(data) = self.drifted(now) else {
+            return Ok(self);
+        };
+        ActiveModel {
+            id: Set(self.id),
+            data: Set(data),
+            touched_at: Set(now),
+            ..Default::default()
+        }
+        .update(db)
+        .await
+    }
+
+    /// The node as `regenerate` would leave it now, without writing it.
+    pub fn regenerated(mut self) -> Model {
+        let now = Utc::now();
+        if let Some(data) = self.drifted(now) {
+            self.data = data;
+            self.touched_at = now;
+        }
+        self
+    }
+
+    /// Cells drifted back toward the generated values from `touched_at` to `now`,
+    /// None if no time elapsed or the node does not regenerate: regen is disabled,
+    /// the generator is not deterministic, or the node is imported and the generator
+    /// is not `map`.
+    ///
+    /// Cells are rounded at random in proportion to the fraction, so that a node
+    /// accessed often drifts as fast as one accessed rarely.
+    fn drifted(&self, now: DateTimeUtc) -> Option<Vec<u8>> {
+        let rule = config::rule();
+        let half_life = rule.regen.half_life;
+        if half_life <= 0.0
+            || !world::generator().deterministic()
+            || (self.imported && rule.world.generator != config::Generator::Map)
+        {
+            return None;
+        }
+        let elapsed = (now - self.touched_at).num_milliseconds();
+        if elapsed <= 0 {
+            return None;
+        }
+        let remain = 0.5f32.powf(elapsed as f32 / 1000.0 / half_life);
+
+        let id = NodeID::from_i32(self.id);
+        let baseline: Vec<u8> = world::generate(id).into();
+        let mut rng = SmallRng::from_entropy();
+        let mut data = self.data.clone();
+        // cells beyond the generated ones have nothing to drift back to
+        for (cell, base) in data.iter_mut().zip(baseline) {
+            let (c, b) = (*cell as i8 as f32, base as i8 as f32);
+            let next = b + (c - b) * remain;
+            *cell = (next + rng.gen::<f32>()).floor() as i8 as u8;
+        }
+        Some(data)
+    }
+
+    /// Mean temperature of all cells, None if the node is empty.
+    pub fn mean_temperature(&self) -> Option<f32> {
+        mean_of(&self.data)
+    }
+
     #[instrument(skip(db), err)]
     pub async fn prepare_origin<C: ConnectionTrait>(db: &C) -> Result<(), RuntimeError> {
         if Entity::find_by_id(NodeID::SITU.into_i32())
@@ -60,11 +254,7 @@ impl Model {
             return Ok(());
         };
 
-        let n = ActiveModel {
-            id: Set(NodeID::SITU.into_i32()),
-            data: Set(NodeData::random().into()),
-        };
-        Entity::insert(n)
+        Entity::insert(Self::generate(NodeID::SITU))
             .on_conflict(OnConflict::column(Column::Id).do_nothing().to_owned())
             .do_nothing()
             .exec(db)
@@ -87,6 +277,8 @@ impl Model {
                 let n = ActiveModel {
                     id: Set(self.id),
                     data: Set(self.data),
+                    touched_at: Set(Utc::now()),
+                    ..Default::default()
                 };
                 return Ok(n.update(db).await?);
             }
@@ -124,16 +316,51 @@ impl Model {
         let n = ActiveModel {
             id: Set(self.id),
             data: Set(self.data),
+            touched_at: Set(Utc::now()),
+            ..Default::default()
         };
         Ok(n.update(db).await?)
     }
 
+    /// Shift every cell of one batch of nodes in the regions by `delta` degrees, saturating,
+    /// after the cells drift back for the time elapsed.
+    ///
+    /// Only nodes after `after` in id order are taken, so the regions are walked batch by batch.
+    /// Return the id of the last node shifted, None when no node is left.
+    pub async fn shift_batch<C: ConnectionTrait>(
+        db: &C,
+        regions: &[Region],
+        delta: i16,
+        after: Option<i32>,
+        size: u64,
+    ) -> Result<Option<i32>, DbErr> {
+        let mut q = Entity::find().filter(Region::any_condition(regions, Column::Id));
+        if let Some(after) = after {
+            q = q.filter(Column::Id.gt(after));
+        }
+        let ns = q.order_by_asc(Column::Id).limit(size).all(db).await?;
+        let last = ns.last().map(|n| n.id);
+        let delta = delta.clamp(i8::MIN as i16, i8::MAX as i16) as i8;
+        let now = Utc::now();
+        for n in ns {
+            let mut data = n.drifted(now).unwrap_or(n.data);
+            for cell in data.iter_mut() {
+                *cell = (*cell as i8).saturating_add(delta) as u8;
+            }
+            ActiveModel {
+                id: Set(n.id),
+                data: Set(data),
+                touched_at: Set(now),
+                ..Default::default()
+            }
+            .update(db)
+            .await?;
+        }
+        Ok(last)
+    }
+
     pub async fn _ensure<C: ConnectionTrait>(db: &C, id: FlatID) -> Result<(), OperationError> {
-        let n = ActiveModel {
-            id: Set(id.into()),
-            data: Set(NodeData::random().into()),
-        };
-        Entity::insert(n)
+        Entity::insert(Self::generate(id.into()))
             .on_conflict(OnConflict::column(Column::Id).do_nothing().to_owned())
             .do_nothing()
             .exec(db)
@@ -142,11 +369,51 @@ impl Model {
     }
 }
 
-impl Into<Node> for Model {
-    fn into(self) -> Node {
-        Node{
-            id: NodeID::from_i32(self.id),
-            data: NodeData::from_bytes(self.data),
+/// Mean temperature of cells, None if there is no cell.
+pub fn mean_of(data: &[u8]) -> Option<f32> {
+    if data.is_empty() {
+        return None;
+    }
+    let sum: i64 = data.iter().map(|cell| *cell as i8 as i64).sum();
+    Some(sum as f32 / data.len() as f32)
+}
+
+/// Spread `delta` degrees over the cells except `skip`, one degree a cell at a time,
+/// heat when positive and cool when negative.
+///
+/// Return the degrees left when all cells are saturated.
+pub fn spread(data: &mut [u8], skip: usize, mut delta: i16) -> i16 {
+    let len = data.len();
+    while delta != 0 {
+        let mut progress = false;
+        for i in (skip + 1..len).chain(0..skip.min(len)) {
+            if delta == 0 {
+                break;
+            }
+            let cell = data[i] as i8;
+            let next = if delta > 0 {
+                cell.checked_add(1)
+            } else {
+                cell.checked_sub(1)
+            };
+            if let Some(next) = next {
+                data[i] = next as u8;
+                delta -= delta.signum();
+                progress = true;
+            }
+        }
+        if !progress {
+            break;
+        }
+    }
+    delta
+}
+
+impl From<Model> for Node {
+    fn from(value: Model) -> Self {
+        Node {
+            id: NodeID::from_i32(value.id),
+            data: NodeData::from_bytes(value.data),
         }
     }
 }
@@ -0,0 +1,315 @@
+use chrono::{Duration, Utc};
+use sea_orm::{entity::prelude::*, Condition, IntoActiveModel, QueryOrder, QuerySelect, Set};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::err::OperationError;
+use entropy_base::grid::NodeID;
+
+use super::{claim, guest, permit};
+
+/// An offer on the market, what the poster gives is held in escrow until settled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "offer")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    #[sea_orm(index)]
+    pub master_id: i32,
+    /// guest of the poster, receiving the price or paying it
+    #[sea_orm(index)]
+    pub guest_id: i32,
+    pub kind: Kind,
+    /// energy sold
+    pub amount: i64,
+    /// guest sold
+    #[sea_orm(index)]
+    pub goods_id: Option<i32>,
+    /// node to access
+    #[serde(serialize_with = "ser_opt_flat", deserialize_with = "de_opt_flat")]
+    pub node_id: Option<i32>,
+    /// seconds of the node access
+    pub duration: i64,
+    pub price: i64,
+    #[sea_orm(index)]
+    pub status: Status,
+    pub taker_id: Option<i32>,
+    pub created_at: DateTimeUtc,
+    pub expire_at: DateTimeUtc,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    /// sell `amount` energy, held in escrow
+    #[sea_orm(string_value = "energy")]
+    Energy,
+    /// sell the guest `goods_id`, locked until settled
+    #[sea_orm(string_value = "guest")]
+    Guest,
+    /// buy access to `node_id` from its claimant, the price is held in escrow
+    #[sea_orm(string_value = "access")]
+    Access,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    #[sea_orm(string_value = "open")]
+    Open,
+    #[sea_orm(string_value = "accepted")]
+    Accepted,
+    #[sea_orm(string_value = "cancelled")]
+    Cancelled,
+    #[sea_orm(string_value = "expired")]
+    Expired,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::player::Entity",
+        from = "Column::MasterId",
+        to = "super::player::Column::Id"
+    )]
+    Player,
+}
+
+impl Related<super::player::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Player.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+fn ser_opt_flat<S: Serializer>(id: &Option<i32>, serializer: S) -> Result<S::Ok, S::Error> {
+    id.map(NodeID::from_i32).serialize(serializer)
+}
+
+fn de_opt_flat<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<i32>, D::Error> {
+    Ok(Option::<NodeID>::deserialize(deserializer)?.map(NodeID::into_i32))
+}
+
+/// What the poster puts on the market.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Goods {
+    Energy { amount: i64 },
+    Guest { guest: i32 },
+    Access { node: NodeID, duration: i64 },
+}
+
+/// Whether the guest is for sale, a guest for sale cannot be used.
+pub async fn locked<C: ConnectionTrait>(db: &C, gid: i32) -> Result<bool, DbErr> {
+    Ok(Entity::find()
+        .filter(
+            Condition::all()
+                .add(Column::GoodsId.eq(gid))
+                .add(Column::Status.eq(Status::Open))
+                .add(Column::ExpireAt.gt(Utc::now())),
+        )
+        .count(db)
+        .await?
+        > 0)
+}
+
+/// Settle the expired offers, the escrow goes back to the posters.
+pub async fn expire_due<C: ConnectionTrait>(db: &C) -> Result<(), OperationError> {
+    let due = Entity::find()
+        .filter(Column::Status.eq(Status::Open))
+        .filter(Column::ExpireAt.lte(Utc::now()))
+        .all(db)
+        .await?;
+    for o in due {
+        o.close(db, Status::Expired).await?;
+    }
+    Ok(())
+}
+
+impl Model {
+    /// Post the offer, the goods are taken into escrow.
+    pub async fn post<C: ConnectionTrait>(
+        db: &C,
+        poster: guest::Model,
+        goods: Goods,
+        price: i64,
+        expire: i64,
+    ) -> Result<Model, OperationError> {
+        let mut o = ActiveModel {
+            master_id: Set(poster.master_id),
+            guest_id: Set(poster.id),
+            amount: Set(0),
+            goods_id: Set(None),
+            node_id: Set(None),
+            duration: Set(0),
+            price: Set(price),
+            status: Set(Status::Open),
+            taker_id: Set(None),
+            created_at: Set(Utc::now()),
+            expire_at: Set(Utc::now() + Duration::seconds(expire)),
+            ..Default::default()
+        };
+        match goods {
+            Goods::Energy { amount } => {
+                poster.consume_energy(db, amount).await?;
+                o.kind = Set(Kind::Energy);
+                o.amount = Set(amount);
+            }
+            Goods::Guest { guest } => {
+                let g = guest::Entity::find_by_id(guest)
+                    .one(db)
+                    .await?
+                    .filter(|g| g.master_id == poster.master_id)
+                    .ok_or(OperationError::GuestNotExist(guest))?;
+                // the guest must not receive the price of any offer, or the price goes with it
+                if g.id == poster.id || Self::paying_to(db, g.id).await? {
+                    return Err(OperationError::GuestInOffer(g.id));
+                }
+                if locked(db, g.id).await? {
+                    return Err(OperationError::GuestLocked(g.id));
+                }
+                o.kind = Set(Kind::Guest);
+                o.goods_id = Set(Some(g.id));
+            }
+            Goods::Access { node, duration } => {
+                poster.consume_energy(db, price).await?;
+                o.kind = Set(Kind::Access);
+                o.node_id = Set(Some(node.into_i32()));
+                o.duration = Set(duration);
+            }
+        }
+        Ok(o.insert(db).await?)
+    }
+
+    /// Whether the guest is the poster of any open offer.
+    async fn paying_to<C: ConnectionTrait>(db: &C, gid: i32) -> Result<bool, DbErr> {
+        Ok(Entity::find()
+            .filter(Column::GuestId.eq(gid))
+            .filter(Column::Status.eq(Status::Open))
+            .count(db)
+            .await?
+            > 0)
+    }
+
+    pub async fn count_open<C: ConnectionTrait>(db: &C, master_id: i32) -> Result<u64, DbErr> {
+        Entity::find()
+            .filter(Column::MasterId.eq(master_id))
+            .filter(Column::Status.eq(Status::Open))
+            .count(db)
+            .await
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.status == Status::Open && self.expire_at > Utc::now()
+    }
+
+    /// Close the offer without a taker, the escrow goes back to the poster.
+    pub async fn close<C: ConnectionTrait>(
+        self,
+        db: &C,
+        status: Status,
+    ) -> Result<Model, OperationError> {
+        let refund = match self.kind {
+            Kind::Energy => self.amount,
+            Kind::Guest => 0,
+            Kind::Access => self.price,
+        };
+        if refund > 0 {
+            if let Some(g) = guest::Entity::find_by_id(self.guest_id).one(db).await? {
+                g.gain_energy(db, refund).await?;
+            }
+        }
+        let mut o = self.into_active_model();
+        o.status = Set(status);
+        Ok(o.update(db).await?)
+    }
+
+    /// Settle the offer with the taker, all in the transaction of `db`.
+    pub async fn accept<C: ConnectionTrait>(
+        self,
+        db: &C,
+        taker: guest::Model,
+    ) -> Result<Model, OperationError> {
+        if !self.is_open() {
+            return Err(OperationError::OfferNotOpen(self.id));
+        }
+        if taker.master_id == self.master_id {
+            return Err(OperationError::OfferOwn(self.id));
+        }
+        let poster = guest::Entity::find_by_id(self.guest_id)
+            .one(db)
+            .await?
+            .ok_or(OperationError::GuestNotExist(self.guest_id))?;
+
+        match self.kind {
+            Kind::Energy => {
+                let taker = taker.consume_energy(db, self.price).await?;
+                taker.gain_energy(db, self.amount).await?;
+                poster.gain_energy(db, self.price).await?;
+            }
+            Kind::Guest => {
+                let gid = self.goods_id.unwrap_or_default();
+                let g = guest::Entity::find_by_id(gid)
+                    .one(db)
+                    .await?
+                    .filter(|g| g.master_id == self.master_id)
+                    .ok_or(OperationError::GuestNotExist(gid))?;
+                taker.consume_energy(db, self.price).await?;
+                poster.gain_energy(db, self.price).await?;
+                let mut g = g.into_active_model();
+                g.master_id = Set(taker.master_id);
+                g.update(db).await?;
+            }
+            Kind::Access => {
+                let node = NodeID::from_i32(self.node_id.unwrap_or_default());
+                claim::Model::find_active(db, node)
+                    .await?
+                    .filter(|c| c.master_id == taker.master_id)
+                    .ok_or(OperationError::ClaimNotExist(node))?;
+                taker.gain_energy(db, self.price).await?;
+                permit::Model::grant(db, node, self.master_id, taker.master_id, self.duration)
+                    .await?;
+            }
+        }
+
+        let mut o = self.into_active_model();
+        o.status = Set(Status::Accepted);
+        o.taker_id = Set(Some(taker.master_id));
+        Ok(o.update(db).await?)
+    }
+
+    /// Open offers, newest first.
+    pub async fn list_open<C: ConnectionTrait>(
+        db: &C,
+        kind: Option<Kind>,
+        limit: u64,
+    ) -> Result<Vec<Model>, OperationError> {
+        let mut q = Entity::find()
+            .filter(Column::Status.eq(Status::Open))
+            .filter(Column::ExpireAt.gt(Utc::now()));
+        if let Some(kind) = kind {
+            q = q.filter(Column::Kind.eq(kind));
+        }
+        Ok(q.order_by_desc(Column::Id).limit(limit).all(db).await?)
+    }
+
+    /// Offers posted or taken by the player, newest first.
+    pub async fn list_involving<C: ConnectionTrait>(
+        db: &C,
+        master_id: i32,
+        limit: u64,
+    ) -> Result<Vec<Model>, OperationError> {
+        Ok(Entity::find()
+            .filter(
+                Condition::any()
+                    .add(Column::MasterId.eq(master_id))
+                    .add(Column::TakerId.eq(master_id)),
+            )
+            .order_by_desc(Column::Id)
+            .limit(limit)
+            .all(db)
+            .await?)
+    }
+}
@@ -0,0 +1,94 @@
+use chrono::{Duration, Utc};
+use sea_orm::{entity::prelude::*, Condition, Set};
+use serde::{Deserialize, Serialize};
+
+use entropy_base::grid::NodeID;
+
+/// A player allowed to use a claimed node freely, granted by the claimant.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "permit")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    #[sea_orm(index)]
+    #[serde(
+        serialize_with = "entropy_base::grid::ser_flat",
+        deserialize_with = "entropy_base::grid::de_flat"
+    )]
+    pub pos: i32,
+    #[sea_orm(index)]
+    pub player_id: i32,
+    /// the claimant granting, the permit is void once the node is claimed by others
+    pub grantor_id: i32,
+    pub expire_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::player::Entity",
+        from = "Column::PlayerId",
+        to = "super::player::Column::Id"
+    )]
+    Player,
+    #[sea_orm(
+        belongs_to = "super::node::Entity",
+        from = "Column::Pos",
+        to = "super::node::Column::Id"
+    )]
+    Node,
+}
+
+impl Related<super::player::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Player.def()
+    }
+}
+
+impl Related<super::node::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Node.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// Whether the player holds a permit to the node granted by the claimant.
+pub async fn permitted<C: ConnectionTrait>(
+    db: &C,
+    node: NodeID,
+    player_id: i32,
+    grantor_id: i32,
+) -> Result<bool, DbErr> {
+    Ok(Entity::find()
+        .filter(
+            Condition::all()
+                .add(Column::Pos.eq(node.into_i32()))
+                .add(Column::PlayerId.eq(player_id))
+                .add(Column::GrantorId.eq(grantor_id))
+                .add(Column::ExpireAt.gt(Utc::now())),
+        )
+        .count(db)
+        .await?
+        > 0)
+}
+
+impl Model {
+    pub async fn grant<C: ConnectionTrait>(
+        db: &C,
+        node: NodeID,
+        player_id: i32,
+        grantor_id: i32,
+        duration: i64,
+    ) -> Result<Model, DbErr> {
+        ActiveModel {
+            pos: Set(node.into_i32()),
+            player_id: Set(player_id),
+            grantor_id: Set(grantor_id),
+            expire_at: Set(Utc::now() + Duration::seconds(duration)),
+            ..Default::default()
+        }
+        .insert(db)
+        .await
+    }
+}
@@ -1,8 +1,7 @@
 use crate::err::OperationError;
-use entropy_base::grid::NodeID;
 
-use super::guest;
-use sea_orm::{entity::prelude::*, QuerySelect};
+use super::{guest, offer, spawn};
+use sea_orm::{entity::prelude::*, DatabaseTransaction, IntoActiveModel, QuerySelect, Set};
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
@@ -12,12 +11,34 @@ pub struct Model {
     pub id: i32,
     pub name: String,
     pub password: String,
+    #[sea_orm(index)]
+    pub alliance_id: Option<i32>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
     #[sea_orm(has_many = "super::guest::Entity")]
     Guest,
+    #[sea_orm(has_many = "super::engine::Entity")]
+    Engine,
+    #[sea_orm(has_many = "super::battery::Entity")]
+    Battery,
+    #[sea_orm(has_many = "super::claim::Entity")]
+    Claim,
+    #[sea_orm(has_many = "super::invite::Entity")]
+    Invite,
+    #[sea_orm(has_many = "super::offer::Entity")]
+    Offer,
+    #[sea_orm(has_many = "super::visit::Entity")]
+    Visit,
+    #[sea_orm(has_many = "super::achievement::Entity")]
+    Achievement,
+    #[sea_orm(
+        belongs_to = "super::alliance::Entity",
+        from = "Column::AllianceId",
+        to = "super::alliance::Column::Id"
+    )]
+    Alliance,
 }
 
 impl Related<super::guest::Entity> for Entity {
@@ -26,6 +47,54 @@ impl Related<super::guest::Entity> for Entity {
     }
 }
 
+impl Related<super::engine::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Engine.def()
+    }
+}
+
+impl Related<super::battery::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Battery.def()
+    }
+}
+
+impl Related<super::claim::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Claim.def()
+    }
+}
+
+impl Related<super::invite::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Invite.def()
+    }
+}
+
+impl Related<super::offer::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Offer.def()
+    }
+}
+
+impl Related<super::visit::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Visit.def()
+    }
+}
+
+impl Related<super::achievement::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Achievement.def()
+    }
+}
+
+impl Related<super::alliance::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Alliance.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
 
 impl Model {
@@ -37,7 +106,11 @@ impl Model {
         &self,
         db: &C,
     ) -> Result<Vec<guest::Model>, OperationError> {
-        Ok(self.find_related(guest::Entity).all(db).await?)
+        let mut gs = Vec::new();
+        for g in self.find_related(guest::Entity).all(db).await? {
+            gs.push(g.equalize(db).await?);
+        }
+        Ok(gs)
     }
 
     pub async fn list_guest_id<C: ConnectionTrait>(
@@ -52,12 +125,13 @@ impl Model {
             .await?)
     }
 
-    pub async fn spawn_guest<C: ConnectionTrait>(
+    pub async fn spawn_guest(
         &self,
-        db: &C,
+        txn: &DatabaseTransaction,
     ) -> Result<guest::Model, OperationError> {
-        if self.count_guest(db).await? == 0 {
-            Ok(guest::Model::spawn(db, NodeID::SITU.into(), self.id).await?)
+        if self.count_guest(txn).await? == 0 {
+            let at = spawn::choose(txn).await?;
+            Ok(guest::Model::spawn(txn, at, self.id).await?)
         } else {
             Err(OperationError::AlreadyHasGuest)
         }
@@ -71,9 +145,22 @@ impl Model {
         let g = guest::Entity::find_by_id(gid).one(db).await?;
         if let Some(g) = g {
             if g.master_id == self.id {
-                return Ok(g);
+                if offer::locked(db, gid).await? {
+                    return Err(OperationError::GuestLocked(gid));
+                }
+                return g.equalize(db).await;
             }
         };
         Err(OperationError::GuestNotExist(gid))
     }
+
+    pub async fn join_alliance<C: ConnectionTrait>(
+        self,
+        db: &C,
+        alliance_id: Option<i32>,
+    ) -> Result<Model, OperationError> {
+        let mut p = self.into_active_model();
+        p.alliance_id = Set(alliance_id);
+        Ok(p.update(db).await?)
+    }
 }
@@ -1,4 +1,4 @@
-use sea_orm::{ConnectionTrait, DbConn, Schema};
+use sea_orm::{sea_query::Table, ConnectionTrait, DbConn, Schema};
 use tracing::instrument;
 
 use crate::err::RuntimeError;
@@ -13,17 +13,86 @@ pub async fn ensure_schema(db: &DbConn) -> Result<(), RuntimeError> {
         schema.create_table_from_entity(super::node::Entity),
         schema.create_table_from_entity(super::player::Entity),
         schema.create_table_from_entity(super::guest::Entity),
+        schema.create_table_from_entity(super::engine::Entity),
+        schema.create_table_from_entity(super::battery::Entity),
+        schema.create_table_from_entity(super::siphon::Entity),
+        schema.create_table_from_entity(super::claim::Entity),
+        schema.create_table_from_entity(super::alliance::Entity),
+        schema.create_table_from_entity(super::invite::Entity),
+        schema.create_table_from_entity(super::message::Entity),
+        schema.create_table_from_entity(super::offer::Entity),
+        schema.create_table_from_entity(super::permit::Entity),
+        schema.create_table_from_entity(super::visit::Entity),
+        schema.create_table_from_entity(super::leaderboard::Entity),
+        schema.create_table_from_entity(super::achievement::Entity),
+        schema.create_table_from_entity(super::season::Entity),
+        schema.create_table_from_entity(super::event::Entity),
+        schema.create_table_from_entity(super::spawn::Entity),
     ];
     let index_stmts = vec![
         schema.create_index_from_entity(super::node::Entity),
         schema.create_index_from_entity(super::player::Entity),
         schema.create_index_from_entity(super::guest::Entity),
+        schema.create_index_from_entity(super::engine::Entity),
+        schema.create_index_from_entity(super::battery::Entity),
+        schema.create_index_from_entity(super::siphon::Entity),
+        schema.create_index_from_entity(super::claim::Entity),
+        schema.create_index_from_entity(super::alliance::Entity),
+        schema.create_index_from_entity(super::invite::Entity),
+        schema.create_index_from_entity(super::message::Entity),
+        schema.create_index_from_entity(super::offer::Entity),
+        schema.create_index_from_entity(super::permit::Entity),
+        schema.create_index_from_entity(super::visit::Entity),
+        schema.create_index_from_entity(super::leaderboard::Entity),
+        schema.create_index_from_entity(super::achievement::Entity),
+        schema.create_index_from_entity(super::season::Entity),
+        schema.create_index_from_entity(super::event::Entity),
+        schema.create_index_from_entity(super::spawn::Entity),
+    ];
+
+    // Columns introduced after their tables, for the databases created before
+    let column_stmts = vec![
+        Table::alter()
+            .table(super::guest::Entity)
+            .add_column_if_not_exists(
+                &mut schema.get_column_def::<super::guest::Entity>(super::guest::Column::ThermalAt),
+            )
+            .to_owned(),
+        Table::alter()
+            .table(super::player::Entity)
+            .add_column_if_not_exists(
+                &mut schema
+                    .get_column_def::<super::player::Entity>(super::player::Column::AllianceId),
+            )
+            .to_owned(),
+        Table::alter()
+            .table(super::node::Entity)
+            .add_column_if_not_exists(
+                &mut schema.get_column_def::<super::node::Entity>(super::node::Column::Terrain),
+            )
+            .to_owned(),
+        Table::alter()
+            .table(super::node::Entity)
+            .add_column_if_not_exists(
+                &mut schema.get_column_def::<super::node::Entity>(super::node::Column::TouchedAt),
+            )
+            .to_owned(),
+        Table::alter()
+            .table(super::node::Entity)
+            .add_column_if_not_exists(
+                &mut schema.get_column_def::<super::node::Entity>(super::node::Column::Imported),
+            )
+            .to_owned(),
     ];
 
     for mut i in table_stmts {
         db.execute(db.get_database_backend().build(i.if_not_exists()))
             .await?;
     }
+    // before the indexes, which may cover the columns added
+    for i in column_stmts {
+        db.execute(db.get_database_backend().build(&i)).await?;
+    }
     for mut i in index_stmts.into_iter().flatten() {
         db.execute(db.get_database_backend().build(i.if_not_exists()))
             .await?;
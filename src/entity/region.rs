@@ -0,0 +1,80 @@
+use sea_orm::{
+    sea_query::{Expr, SimpleExpr},
+    ColumnTrait, Condition,
+};
+use serde::{Deserialize, Serialize};
+
+use entropy_base::grid::NodeID;
+
+/// Widest region whose columns are matched one id range each.
+const MAX_COLUMNS: i32 = 64;
+
+/// A rectangle of nodes, bounds included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Region {
+    pub x0: i16,
+    pub y0: i16,
+    pub x1: i16,
+    pub y1: i16,
+}
+
+impl Region {
+    /// Region between two corners in any order.
+    pub fn new(one: NodeID, other: NodeID) -> Self {
+        Self {
+            x0: one.0.min(other.0),
+            y0: one.1.min(other.1),
+            x1: one.0.max(other.0),
+            y1: one.1.max(other.1),
+        }
+    }
+
+    /// Nodes at most `radius` from the center, moving diagonally counts as one.
+    pub fn around(center: NodeID, radius: i16) -> Self {
+        let r = radius.max(0);
+        Self::new(
+            NodeID(center.0.saturating_sub(r), center.1.saturating_sub(r)),
+            NodeID(center.0.saturating_add(r), center.1.saturating_add(r)),
+        )
+    }
+
+    /// Condition of a flattened node id column inside the region.
+    ///
+    /// x is the high half of the flat id and y is the low half, so every column of the region
+    /// is one or two (when y crosses 0) ranges of ids, which the primary key index can serve.
+    /// A region wider than [`MAX_COLUMNS`] takes the band of ids of its columns instead
+    /// and filters y afterwards, shifting arithmetically as both halves are signed.
+    pub fn condition<C: ColumnTrait>(&self, col: C) -> SimpleExpr {
+        let flat = |x: i16, y: i16| NodeID(x, y).into_i32();
+        let between = |lo: i32, hi: i32| Expr::col(col.as_column_ref()).between(lo, hi);
+        if (self.x1 as i32 - self.x0 as i32) >= MAX_COLUMNS {
+            return between(flat(self.x0, 0), flat(self.x1, -1)).and(Expr::cust_with_exprs(
+                "(($1 << 16) >> 16) BETWEEN $2 AND $3",
+                [
+                    Expr::col(col.as_column_ref()).into(),
+                    Expr::val(self.y0 as i32).into(),
+                    Expr::val(self.y1 as i32).into(),
+                ],
+            ));
+        }
+        let mut ys = vec![(self.y0, self.y1)];
+        if self.y0 < 0 && self.y1 >= 0 {
+            ys = vec![(self.y0, -1), (0, self.y1)];
+        }
+        (self.x0..=self.x1)
+            .flat_map(|x| ys.iter().map(move |&(y0, y1)| (x, y0, y1)))
+            .map(|(x, y0, y1)| between(flat(x, y0), flat(x, y1)))
+            .reduce(SimpleExpr::or)
+            .expect("a region has at least one column")
+    }
+
+    /// Condition of a flattened node id column inside any of the regions, false if there is none.
+    pub fn any_condition<C: ColumnTrait>(regions: &[Region], col: C) -> Condition {
+        if regions.is_empty() {
+            return Condition::all().add(Expr::cust("FALSE"));
+        }
+        regions
+            .iter()
+            .fold(Condition::any(), |c, r| c.add(r.condition(col)))
+    }
+}
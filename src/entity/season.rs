@@ -0,0 +1,155 @@
+use std::path::Path;
+
+use chrono::{Duration, Utc};
+use sea_orm::{
+    entity::prelude::*, AccessMode, DbConn, IntoActiveModel, IsolationLevel, QueryOrder, Set,
+    TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs::{self, File},
+    io::{AsyncWrite, AsyncWriteExt, BufWriter},
+};
+use tracing::{info, instrument};
+
+use crate::err::RuntimeError;
+
+use super::{
+    battery, claim, engine, event, guest, leaderboard, message, node, offer, permit, siphon, visit,
+};
+
+/// A season of the world, the world is archived and reset between seasons.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "season")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub started_at: DateTimeUtc,
+    /// when the season is planned to end
+    pub end_at: DateTimeUtc,
+    /// when the season actually ended, None for the current season
+    pub ended_at: Option<DateTimeUtc>,
+    /// dump file of the world at the end of the season
+    pub archive: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl Model {
+    pub async fn current<C: ConnectionTrait>(db: &C) -> Result<Option<Model>, DbErr> {
+        Entity::find().order_by_desc(Column::Id).one(db).await
+    }
+
+    /// The current season, the first season starts now if there is none.
+    pub async fn ensure_current<C: ConnectionTrait>(db: &C, length: i64) -> Result<Model, DbErr> {
+        match Self::current(db).await? {
+            Some(s) => Ok(s),
+            None => Self::start(db, length).await,
+        }
+    }
+
+    async fn start<C: ConnectionTrait>(db: &C, length: i64) -> Result<Model, DbErr> {
+        let now = Utc::now();
+        ActiveModel {
+            started_at: Set(now),
+            end_at: Set(now + Duration::seconds(length)),
+            ended_at: Set(None),
+            archive: Set(None),
+            ..Default::default()
+        }
+        .insert(db)
+        .await
+    }
+
+    /// End the current season and start a new one lasting `length` seconds.
+    ///
+    /// Nodes, guests and the leaderboard are dumped into a msgpack file under `dir`,
+    /// then everything of the world is cleared and the origin is prepared again.
+    /// Players, alliances and achievements are kept, so are the spawn points,
+    /// which come with the map rather than the season.
+    #[instrument(skip(db), err)]
+    pub async fn rotate(db: &DbConn, dir: &Path, length: i64) -> Result<Model, RuntimeError> {
+        leaderboard::refresh(db).await?;
+
+        let txn = db
+            .begin_with_config(
+                Some(IsolationLevel::RepeatableRead),
+                Some(AccessMode::ReadWrite),
+            )
+            .await?;
+        let mut season = Self::ensure_current(&txn, length).await?;
+        season.ended_at = Some(Utc::now());
+
+        // archive
+        fs::create_dir_all(dir).await?;
+        let path = dir.join(format!("season-{}.msgpack", season.id));
+        info!("archiving season {} into {}", season.id, path.display());
+        season.archive = Some(path.display().to_string());
+        let mut out = BufWriter::new(File::create(&path).await?);
+        let mut buf = Vec::new();
+        rmp::encode::write_map_len(&mut buf, 4).map_err(rmp_serde::encode::Error::from)?;
+        rmp::encode::write_str(&mut buf, "season").map_err(rmp_serde::encode::Error::from)?;
+        rmp_serde::encode::write_named(&mut buf, &season)?;
+        out.write_all(&buf).await?;
+        dump::<node::Entity, _>(&txn, &mut out, "node").await?;
+        dump::<guest::Entity, _>(&txn, &mut out, "guest").await?;
+        dump::<leaderboard::Entity, _>(&txn, &mut out, "leaderboard").await?;
+        out.flush().await?;
+
+        // reset, tables referring to others first, spawn points are kept with the map
+        info!("resetting the world");
+        engine::Entity::delete_many().exec(&txn).await?;
+        battery::Entity::delete_many().exec(&txn).await?;
+        siphon::Entity::delete_many().exec(&txn).await?;
+        claim::Entity::delete_many().exec(&txn).await?;
+        message::Entity::delete_many().exec(&txn).await?;
+        offer::Entity::delete_many().exec(&txn).await?;
+        permit::Entity::delete_many().exec(&txn).await?;
+        visit::Entity::delete_many().exec(&txn).await?;
+        guest::Entity::delete_many().exec(&txn).await?;
+        leaderboard::Entity::delete_many().exec(&txn).await?;
+        event::Entity::delete_many().exec(&txn).await?;
+        node::Entity::delete_many().exec(&txn).await?;
+        node::Model::prepare_origin(&txn).await?;
+
+        let mut s = season.clone().into_active_model();
+        s.ended_at = Set(season.ended_at);
+        s.archive = Set(season.archive);
+        s.update(&txn).await?;
+        let next = Self::start(&txn, length).await?;
+        txn.commit().await?;
+        info!("season {} started", next.id);
+        Ok(next)
+    }
+}
+
+/// Write all the rows of the table as a msgpack array under `key`, page by page,
+/// each page is encoded in memory and written without blocking the runtime.
+async fn dump<E, C>(
+    db: &C,
+    out: &mut (impl AsyncWrite + Unpin),
+    key: &str,
+) -> Result<(), RuntimeError>
+where
+    E: EntityTrait,
+    E::Model: Serialize + Sync,
+    C: ConnectionTrait,
+{
+    let mut pages = E::find().paginate(db, 1024);
+    let count = pages.num_items().await?;
+    let mut buf = Vec::new();
+    rmp::encode::write_str(&mut buf, key).map_err(rmp_serde::encode::Error::from)?;
+    rmp::encode::write_array_len(&mut buf, count as u32).map_err(rmp_serde::encode::Error::from)?;
+    out.write_all(&buf).await?;
+    while let Some(ms) = pages.fetch_and_next().await? {
+        buf.clear();
+        for m in ms {
+            rmp_serde::encode::write_named(&mut buf, &m)?;
+        }
+        out.write_all(&buf).await?;
+    }
+    Ok(())
+}
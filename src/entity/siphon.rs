@@ -0,0 +1,130 @@
+use chrono::{Duration, Utc};
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use sea_orm::{entity::prelude::*, Condition, QueryOrder, QuerySelect, Set};
+use serde::{Deserialize, Serialize};
+
+use crate::{config, err::OperationError};
+
+use super::guest::{self, get_carnot_efficiency};
+
+/// Record of a siphon attempt, kept so the victim can see who attacked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "siphon")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    #[serde(
+        serialize_with = "entropy_base::grid::ser_flat",
+        deserialize_with = "entropy_base::grid::de_flat"
+    )]
+    pub pos: i32,
+    pub attacker_id: i32,
+    #[sea_orm(index)]
+    pub attacker_master_id: i32,
+    #[sea_orm(index)]
+    pub victim_id: i32,
+    #[sea_orm(index)]
+    pub victim_master_id: i32,
+    pub success: bool,
+    /// energy drained from the victim
+    pub energy: i64,
+    pub created_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl Model {
+    /// Try to drain energy of `victim` into `attacker`.
+    ///
+    /// The chance to succeed is the share of attacker energy in both guests,
+    /// the energy drained is the victim's spare energy times the carnot efficiency
+    /// between both guests.
+    ///
+    /// Return the record and both guests updated.
+    pub async fn attempt<C: ConnectionTrait>(
+        db: &C,
+        attacker: guest::Model,
+        victim: guest::Model,
+    ) -> Result<(Model, guest::Model, guest::Model), OperationError> {
+        let rule = &config::rule().siphon;
+
+        // defence, a victim recently drained is protected for a while
+        let now = Utc::now();
+        let protected = Entity::find()
+            .filter(
+                Condition::all()
+                    .add(Column::VictimId.eq(victim.id))
+                    .add(Column::Success.eq(true))
+                    .add(Column::CreatedAt.gt(now - Duration::seconds(rule.protection))),
+            )
+            .count(db)
+            .await?
+            > 0;
+        if protected {
+            return Err(OperationError::GuestProtected(victim.id));
+        }
+        let attacker = attacker.consume_energy(db, rule.cost).await?;
+
+        let total = attacker.energy + victim.energy;
+        let share = if total > 0 {
+            attacker.energy as f64 / total as f64
+        } else {
+            0.0
+        };
+        let success = SmallRng::from_entropy().gen_bool(share.clamp(0.0, 1.0));
+
+        let spare = (victim.energy - rule.reserve).max(0);
+        let efficiency =
+            get_carnot_efficiency(attacker.temperature as i8, victim.temperature as i8);
+        let energy = if success {
+            ((spare as f64 * efficiency as f64 * rule.max_ratio) as i64).clamp(0, spare)
+        } else {
+            0
+        };
+
+        let (attacker, victim) = if energy > 0 {
+            (
+                attacker.gain_energy(db, energy).await?,
+                victim.consume_energy(db, energy).await?,
+            )
+        } else {
+            (attacker, victim)
+        };
+
+        let record = ActiveModel {
+            pos: Set(victim.pos),
+            attacker_id: Set(attacker.id),
+            attacker_master_id: Set(attacker.master_id),
+            victim_id: Set(victim.id),
+            victim_master_id: Set(victim.master_id),
+            success: Set(success),
+            energy: Set(energy),
+            created_at: Set(now),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+        Ok((record, attacker, victim))
+    }
+
+    /// Recent siphon records involving the player, newest first.
+    pub async fn list_recent<C: ConnectionTrait>(
+        db: &C,
+        master_id: i32,
+        limit: u64,
+    ) -> Result<Vec<Model>, OperationError> {
+        Ok(Entity::find()
+            .filter(
+                Condition::any()
+                    .add(Column::VictimMasterId.eq(master_id))
+                    .add(Column::AttackerMasterId.eq(master_id)),
+            )
+            .order_by_desc(Column::Id)
+            .limit(limit)
+            .all(db)
+            .await?)
+    }
+}
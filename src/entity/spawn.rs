@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+
+use rand::{rngs::SmallRng, seq::SliceRandom, Rng, SeedableRng};
+use sea_orm::{entity::prelude::*, sea_query::OnConflict, DatabaseTransaction, QuerySelect, Set};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{self, SpawnStrategy},
+    err::OperationError,
+    world,
+};
+use entropy_base::grid::NodeID;
+
+use super::{
+    guest::{self, carnot_exchange},
+    node::{self, Terrain},
+    region::Region,
+};
+
+/// Farthest the `least_populated` strategy looks, so that the nodes counted stay few.
+const MAX_POPULATED_RADIUS: i16 = 64;
+
+/// A node where the first guests of players spawn, designated by a map,
+/// used by the `points` spawn strategy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "spawn_point")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    #[serde(
+        serialize_with = "entropy_base::grid::ser_flat",
+        deserialize_with = "entropy_base::grid::de_flat"
+    )]
+    pub pos: i32,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// Node where the first guest of a player spawns, by `rule.spawn.strategy`.
+///
+/// Walls and exhausted nodes are passed over for random nodes within the radius,
+/// the spawn fails if those are all exhausted too. Candidates are only read: nodes
+/// generated are checked after regen, leaving their engines to run when the guest
+/// arrives, the others as they would be generated; only the node chosen is generated if not yet.
+pub async fn choose(txn: &DatabaseTransaction) -> Result<NodeID, OperationError> {
+    let rule = &config::rule().spawn;
+    let area = Region::around(NodeID::SITU, rule.radius);
+    let mut rng = SmallRng::from_entropy();
+    let mut candidates = match rule.strategy {
+        SpawnStrategy::Origin => vec![NodeID::SITU],
+        SpawnStrategy::Random => vec![],
+        SpawnStrategy::LeastPopulated => {
+            let area = world::around(NodeID::SITU, rule.radius.min(MAX_POPULATED_RADIUS));
+            let mut nodes = least_populated(txn, &area, &mut rng).await?;
+            nodes.truncate(rule.attempts as usize);
+            nodes
+        }
+        SpawnStrategy::Points => {
+            let mut points: Vec<_> = Entity::find()
+                .all(txn)
+                .await?
+                .into_iter()
+                .map(|p| NodeID::from_i32(p.pos))
+                .collect();
+            if points.is_empty() {
+                points.push(NodeID::SITU);
+            }
+            points.shuffle(&mut rng);
+            points
+        }
+    };
+    candidates.extend((0..rule.attempts).map(|_| {
+        NodeID(
+            rng.gen_range(area.x0..=area.x1),
+            rng.gen_range(area.y0..=area.y1),
+        )
+    }));
+
+    for at in candidates {
+        // out of a walled world
+        let Ok(at) = world::locate(at) else {
+            continue;
+        };
+        let (terrain, data, new) =
+            if let Some(n) = node::Entity::find_by_id(at.into_i32()).one(txn).await? {
+                let n = n.regenerated();
+                (n.terrain, n.data, None)
+            } else {
+                // the very data checked is inserted, random generators differ every time
+                let new = node::Model::generate(at);
+                (*new.terrain.as_ref(), new.data.as_ref().clone(), Some(new))
+            };
+        if terrain != Terrain::Wall && harvestable(&data) >= rule.min_energy {
+            if let Some(new) = new {
+                node::Entity::insert(new)
+                    .on_conflict(OnConflict::column(node::Column::Id).do_nothing().to_owned())
+                    .do_nothing()
+                    .exec(txn)
+                    .await?;
+            }
+            return Ok(at);
+        }
+    }
+    Err(OperationError::SpawnExhausted)
+}
+
+/// Nodes of the area, as split by `world::around`, the fewest guests on first,
+/// in random order among the same count.
+async fn least_populated<C: ConnectionTrait>(
+    db: &C,
+    area: &[Region],
+    rng: &mut SmallRng,
+) -> Result<Vec<NodeID>, DbErr> {
+    let counts: HashMap<i32, i64> = guest::Entity::find()
+        .select_only()
+        .column(guest::Column::Pos)
+        .column_as(guest::Column::Id.count(), "count")
+        .filter(Region::any_condition(area, guest::Column::Pos))
+        .group_by(guest::Column::Pos)
+        .into_tuple::<(i32, i64)>()
+        .all(db)
+        .await?
+        .into_iter()
+        .collect();
+    let mut nodes: Vec<_> = area
+        .iter()
+        .flat_map(|r| (r.x0..=r.x1).flat_map(move |x| (r.y0..=r.y1).map(move |y| NodeID(x, y))))
+        .collect();
+    nodes.shuffle(rng);
+    nodes.sort_by_key(|id| counts.get(&id.into_i32()).copied().unwrap_or(0));
+    Ok(nodes)
+}
+
+/// Energy a guest at 0 degree harvests from every cell once.
+fn harvestable(data: &[u8]) -> i64 {
+    data.iter()
+        .map(|c| carnot_exchange(0, *c as i8).2 as i64)
+        .sum()
+}
+
+/// Replace all the spawn points.
+pub async fn replace<C: ConnectionTrait>(db: &C, points: &[NodeID]) -> Result<(), DbErr> {
+    Entity::delete_many().exec(db).await?;
+    if !points.is_empty() {
+        Entity::insert_many(points.iter().map(|p| ActiveModel {
+            pos: Set(p.into_i32()),
+        }))
+        .exec_without_returning(db)
+        .await?;
+    }
+    Ok(())
+}
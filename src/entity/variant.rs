@@ -1,4 +1,5 @@
-use sea_orm::{DerivePartialModel, FromQueryResult};
+use entropy_base::grid::{Node, NodeID};
+use sea_orm::{sea_query::Expr, DerivePartialModel, FromQueryResult};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize, DerivePartialModel, FromQueryResult)]
@@ -6,6 +7,7 @@ use serde::{Deserialize, Serialize};
 pub struct PublicPlayer {
     pub id: i32,
     pub name: String,
+    pub alliance_id: Option<i32>,
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, DerivePartialModel, FromQueryResult)]
@@ -19,4 +21,55 @@ pub struct DetectedGuest {
     )]
     pub pos: i32,
     pub master_id: i32,
+    /// alliance of the master, queried with the master joined
+    #[sea_orm(from_expr = "Expr::col((super::player::Entity, super::player::Column::AllianceId))")]
+    pub alliance_id: Option<i32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublicAlliance {
+    #[serde(flatten)]
+    pub alliance: super::alliance::Model,
+    pub members: Vec<PublicPlayer>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ranked {
+    pub rank: u64,
+    #[serde(flatten)]
+    pub entry: super::leaderboard::Model,
+}
+
+/// A node as seen by a player.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum NodeView {
+    Full(TerrainNode),
+    Summary(NodeSummary),
+}
+
+/// A node with its terrain.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TerrainNode {
+    #[serde(flatten)]
+    pub node: Node,
+    pub terrain: super::node::Terrain,
+}
+
+/// What is known of a node hidden in fog.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct NodeSummary {
+    pub id: NodeID,
+    pub fog: bool,
+    /// mean temperature of the cells rounded, None if the node is not generated yet
+    pub temperature: Option<i16>,
+    /// terrain is seen through fog, None if the node is not generated yet
+    pub terrain: Option<super::node::Terrain>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayerProfile {
+    #[serde(flatten)]
+    pub player: PublicPlayer,
+    pub achievements: Vec<super::achievement::Model>,
 }
@@ -0,0 +1,54 @@
+use chrono::Utc;
+use sea_orm::{entity::prelude::*, sea_query::OnConflict, Set};
+use serde::{Deserialize, Serialize};
+
+/// A node a player has ever been on with any guest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "visit")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub player_id: i32,
+    #[sea_orm(primary_key, auto_increment = false)]
+    #[serde(
+        serialize_with = "entropy_base::grid::ser_flat",
+        deserialize_with = "entropy_base::grid::de_flat"
+    )]
+    pub pos: i32,
+    /// when the node is first visited
+    pub visited_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::player::Entity",
+        from = "Column::PlayerId",
+        to = "super::player::Column::Id"
+    )]
+    Player,
+}
+
+impl Related<super::player::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Player.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// Log the node as visited by the player, only the first visit is kept.
+pub async fn record<C: ConnectionTrait>(db: &C, player_id: i32, pos: i32) -> Result<(), DbErr> {
+    Entity::insert(ActiveModel {
+        player_id: Set(player_id),
+        pos: Set(pos),
+        visited_at: Set(Utc::now()),
+    })
+    .on_conflict(
+        OnConflict::columns([Column::PlayerId, Column::Pos])
+            .do_nothing()
+            .to_owned(),
+    )
+    .exec_without_returning(db)
+    .await?;
+    Ok(())
+}
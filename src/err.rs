@@ -41,6 +41,88 @@ pub enum OperationError {
     },
     #[error("cannot exhaust heat <- index:{index}@node:{node:?}")]
     CellTemperatureTooHigh { node: NodeID, index: usize },
+    #[error("two heat sources are the same cell <- index:{index}@node:{node:?}")]
+    SameCell { node: NodeID, index: usize },
+    #[error("engine not exist <- request engine id:{0}")]
+    EngineNotExist(i32),
+    #[error("battery not exist <- request battery id:{0}")]
+    BatteryNotExist(i32),
+    #[error("battery access denied <- request battery id:{0}")]
+    BatteryAccessDenied(i32),
+    #[error("battery full <- require:{require}, room:{room}")]
+    BatteryFull { require: i64, room: i64 },
+    #[error("energy must be positive <- request energy:{0}")]
+    NonPositiveEnergy(i64),
+    #[error("price must not be negative <- request price:{0}")]
+    NegativePrice(i64),
+    #[error("guest is protected from siphon for now <- request guest id:{0}")]
+    GuestProtected(i32),
+    #[error("target must be a guest of other players <- request guest id:{0}")]
+    TargetNotForeign(i32),
+    #[error("node claimed exclusively by other player <- node:{0:?}")]
+    NodeClaimed(NodeID),
+    #[error("node claimed by an ally, cannot be contested <- node:{0:?}")]
+    ClaimAllied(NodeID),
+    #[error("claim not exist <- node:{0:?}")]
+    ClaimNotExist(NodeID),
+    #[error(
+        "stake not enough to contest claim <- node:{node:?}, require:{require}, offer:{offer}"
+    )]
+    ClaimContestFailed {
+        node: NodeID,
+        require: i64,
+        offer: i64,
+    },
+    #[error("stake less than the cost of the duration <- require:{require}, offer:{offer}")]
+    ClaimStakeTooLow { require: i64, offer: i64 },
+    #[error("player already in an alliance <- leave it first")]
+    AlreadyInAlliance,
+    #[error("player not in any alliance <- create or join one first")]
+    NotInAlliance,
+    #[error("alliance not exist <- request alliance id:{0}")]
+    AllianceNotExist(i32),
+    #[error("only the alliance leader can do this <- leader id:{0}")]
+    NotAllianceLeader(i32),
+    #[error("player not a member of the alliance <- request player id:{0}")]
+    NotAllianceMember(i32),
+    #[error("invitation not exist <- request alliance id:{0}")]
+    InviteNotExist(i32),
+    #[error("alliance full <- max members:{max}")]
+    AllianceFull { max: u64 },
+    #[error("target is a guest of an ally <- request guest id:{0}")]
+    TargetAllied(i32),
+    #[error("target must be a guest of the player or an ally <- request guest id:{0}")]
+    TargetNotAllied(i32),
+    #[error("giving energy between guests disabled <- check the rule")]
+    GiveDisabled,
+    #[error("offer not exist <- request offer id:{0}")]
+    OfferNotExist(i32),
+    #[error("offer already settled or expired <- request offer id:{0}")]
+    OfferNotOpen(i32),
+    #[error("cannot accept own offer <- request offer id:{0}")]
+    OfferOwn(i32),
+    #[error("too many open offers <- max:{max}")]
+    OfferLimit { max: u64 },
+    #[error("guest is for sale, cancel the offer to use it <- request guest id:{0}")]
+    GuestLocked(i32),
+    #[error("guest is receiving the price of open offers, cannot be sold <- request guest id:{0}")]
+    GuestInOffer(i32),
+    #[error("node hidden in fog <- visit it or stand by it first, node:{0:?}")]
+    NodeInFog(NodeID),
+    #[error("node out of the world <- node:{0:?}")]
+    NodeOutOfBounds(NodeID),
+    #[error("walk into the wall of the world <- from:{from:?}, direction:{to:?}")]
+    WalkOutOfBounds { from: NodeID, to: navi::Direction },
+    #[error("node impassable <- node:{0:?}")]
+    NodeImpassable(NodeID),
+    #[error("no season started <- start the server once to begin the first season")]
+    SeasonNotStarted,
+    #[error("target not on the same node <- guest at:{here:?}, target at:{there:?}")]
+    NotOnSameNode { here: NodeID, there: NodeID },
+    #[error("no node to spawn on, all exhausted <- nodes recover over time, try again later")]
+    SpawnExhausted,
+    #[error("heatmap too large <- width:{width}, height:{height}, max side:{max}")]
+    HeatmapTooLarge { width: i64, height: i64, max: i64 },
 }
 
 impl From<DbErr> for OperationError {
@@ -59,6 +141,9 @@ pub enum ApiError {
 
     #[error("authorization header error")]
     AuthHeader,
+
+    #[error("admin token missing or wrong <- admin endpoints need http.admin_token set")]
+    AdminDenied,
 }
 
 impl From<DbErr> for ApiError {
@@ -74,9 +159,17 @@ pub enum RuntimeError {
     #[error(transparent)]
     IO(#[from] std::io::Error),
     #[error(transparent)]
+    Encode(#[from] rmp_serde::encode::Error),
+    #[error(transparent)]
     Config(#[from] toml::de::Error),
     #[error(transparent)]
     UrlParse(#[from] url::ParseError),
     #[error(transparent)]
     PgEmbed(#[from] PgEmbedError),
+    #[error("invalid map file <- {0}")]
+    Map(String),
+    #[error("invalid config <- {0}")]
+    InvalidRule(String),
+    #[error(transparent)]
+    Operation(#[from] OperationError),
 }
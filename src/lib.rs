@@ -3,9 +3,26 @@ pub mod config;
 mod db;
 mod entity;
 pub mod err;
+mod world;
+
+pub use entity::region::Region;
+pub use world::heatmap::Shade;
 
 pub async fn start_server(config: config::Root) -> Result<(), err::RuntimeError> {
+    config::init_rule(config.rule)?;
+    world::init_generator()?;
     let db = db::prepare_db(config.db).await?;
+    entity::season::Model::ensure_current(db.as_ref(), config.season.length).await?;
+    tokio::spawn(entity::leaderboard::refresh_periodically(
+        db.as_ref().clone(),
+        config.leaderboard.interval,
+    ));
+    if config.event.enable {
+        tokio::spawn(entity::event::schedule_periodically(
+            db.as_ref().clone(),
+            config.event,
+        ));
+    }
 
     if config.http.enable {
         api::http::http_daemon(config.http, &db).await?;
@@ -15,3 +32,54 @@ pub async fn start_server(config: config::Root) -> Result<(), err::RuntimeError>
     // };
     Ok(())
 }
+
+/// Archive the world of the current season, and reset it for a new season lasting `length` seconds,
+/// `season.length` of the config if None.
+pub async fn end_season(
+    config: config::Root,
+    length: Option<i64>,
+) -> Result<(), err::RuntimeError> {
+    config::init_rule(config.rule)?;
+    world::init_generator()?;
+    let db = db::prepare_db(config.db).await?;
+    let length = length.unwrap_or(config.season.length);
+    config::check_season_length(length)?;
+    entity::season::Model::rotate(db.as_ref(), &config.season.archive_dir, length).await?;
+    Ok(())
+}
+
+/// Write the nodes and the spawn points of the map file into the world.
+pub async fn import_map(
+    config: config::Root,
+    map: &std::path::Path,
+) -> Result<u64, err::RuntimeError> {
+    config::init_rule(config.rule)?;
+    world::init_generator()?;
+    let db = db::prepare_db(config.db).await?;
+    world::map::Map::load(map)?.import(db.as_ref()).await
+}
+
+/// Render the nodes and guests of the region into a PNG heatmap.
+pub async fn render_heatmap(
+    config: config::Root,
+    region: Region,
+    shade: Shade,
+    scale: u16,
+) -> Result<Vec<u8>, err::RuntimeError> {
+    config::init_rule(config.rule)?;
+    world::init_generator()?;
+    let db = db::prepare_db(config.db).await?;
+    Ok(world::heatmap::render(db.as_ref(), region, shade, scale).await?)
+}
+
+/// Generate the nodes of the region not generated yet, `batch` nodes an insert.
+pub async fn pregen(
+    config: config::Root,
+    region: Region,
+    batch: usize,
+) -> Result<u64, err::RuntimeError> {
+    config::init_rule(config.rule)?;
+    world::init_generator()?;
+    let db = db::prepare_db(config.db).await?;
+    world::pregen::pregen(db.as_ref(), region, batch).await
+}
@@ -1,14 +1,9 @@
-pub mod api;
-mod config;
-mod db;
-pub mod entity;
-pub mod err;
-
 use std::path::PathBuf;
 
-use clap::Parser;
-use err::RuntimeError;
-use tracing::{info_span, Level};
+use clap::{Parser, Subcommand};
+use entropy_base::grid::NodeID;
+use entropy_game::{config, err::RuntimeError, Region, Shade};
+use tracing::{info, info_span, Level};
 
 #[tokio::main]
 async fn main() -> Result<(), RuntimeError> {
@@ -30,15 +25,42 @@ async fn main() -> Result<(), RuntimeError> {
         config::read_from_file(cli.config).await?
     };
 
-    let db = db::prepare_db(config.db).await?;
-
-    if config.http.enable {
-        api::http::http_daemon(config.http, &db).await?;
-    };
-    // if config.socket.enable{
-    // api::zmq::socket_daemon(config.socket, &db).await?;
-    // };
-    Ok(())
+    match cli.command {
+        Some(Command::Season { length }) => entropy_game::end_season(config, length).await,
+        Some(Command::Import { map }) => {
+            entropy_game::import_map(config, &map).await?;
+            Ok(())
+        }
+        Some(Command::Heatmap {
+            x0,
+            y0,
+            x1,
+            y1,
+            shade,
+            scale,
+            output,
+        }) => {
+            let region = Region::new(NodeID(x0, y0), NodeID(x1, y1));
+            let png = entropy_game::render_heatmap(config, region, shade, scale).await?;
+            tokio::fs::write(&output, png).await?;
+            info!("heatmap written into {}", output.display());
+            Ok(())
+        }
+        Some(Command::Pregen {
+            region,
+            radius,
+            center,
+            batch,
+        }) => {
+            let region = match (region.as_deref(), radius) {
+                (Some(&[x0, y0, x1, y1]), _) => Region::new(NodeID(x0, y0), NodeID(x1, y1)),
+                _ => Region::around(NodeID(center[0], center[1]), radius.unwrap_or(0)),
+            };
+            entropy_game::pregen(config, region, batch).await?;
+            Ok(())
+        }
+        None => entropy_game::start_server(config).await,
+    }
 }
 
 #[derive(Parser)]
@@ -50,4 +72,62 @@ struct Cli {
     #[arg(help = "path to config file")]
     #[arg(default_value = "entropy.toml")]
     config: PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Archive the world of the current season, and reset it for a new season
+    Season {
+        /// seconds the new season lasts, `season.length` of the config if omitted
+        #[arg(long)]
+        length: Option<i64>,
+    },
+    /// Write the nodes and the spawn points of a map file into the world
+    Import {
+        /// the map file, in the format of `maps/arena.toml`
+        map: PathBuf,
+    },
+    /// Render the nodes and guests of a region into a PNG heatmap
+    Heatmap {
+        /// x of one corner of the region
+        #[arg(allow_negative_numbers = true)]
+        x0: i16,
+        /// y of one corner of the region
+        #[arg(allow_negative_numbers = true)]
+        y0: i16,
+        /// x of the opposite corner
+        #[arg(allow_negative_numbers = true)]
+        x1: i16,
+        /// y of the opposite corner
+        #[arg(allow_negative_numbers = true)]
+        y1: i16,
+        /// which temperature of the cells colours a node
+        #[arg(long, value_enum, default_value_t)]
+        shade: Shade,
+        /// pixels along a side of a node
+        #[arg(long, default_value_t = 4)]
+        scale: u16,
+        /// the PNG file written
+        #[arg(short, long, default_value = "heatmap.png")]
+        output: PathBuf,
+    },
+    /// Generate the nodes of a rectangle or a radius ahead of the players
+    Pregen {
+        /// corners of the rectangle, in any order
+        #[arg(long, num_args = 4, value_names = ["X0", "Y0", "X1", "Y1"])]
+        #[arg(allow_negative_numbers = true, required_unless_present = "radius")]
+        region: Option<Vec<i16>>,
+        /// nodes this far from the center, moving diagonally counts as one
+        #[arg(long, conflicts_with = "region")]
+        radius: Option<i16>,
+        /// center of the radius
+        #[arg(long, num_args = 2, value_names = ["X", "Y"], default_values_t = [0, 0])]
+        #[arg(allow_negative_numbers = true)]
+        center: Vec<i16>,
+        /// nodes inserted in one statement
+        #[arg(long, default_value_t = 1024)]
+        batch: usize,
+    },
 }
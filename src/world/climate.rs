@@ -0,0 +1,153 @@
+use rand::RngCore;
+
+use super::{node_rng, WorldGenerator};
+use crate::{config::Climate, entity::node::Terrain};
+use entropy_base::grid::{NodeData, NodeID, NODE_MAX_SIZE};
+
+/// Temperature fields coherent over nodes, from layered noise.
+pub struct ClimateGenerator {
+    pub seed: u64,
+    pub climate: &'static Climate,
+}
+
+impl WorldGenerator for ClimateGenerator {
+    fn generate(&self, id: NodeID) -> NodeData {
+        climate(self.seed, self.climate, id)
+    }
+
+    /// Hotspots are vents and frozen nodes insulators, elsewhere the terrain noise
+    /// raises walls at its peaks and conductors at its troughs.
+    fn terrain(&self, id: NodeID) -> Terrain {
+        let c = self.climate;
+        match climate_of(self.seed, c, id).1 {
+            Biome::Hotspot => return Terrain::Vent,
+            Biome::Frozen => return Terrain::Insulator,
+            _ => {}
+        }
+        let scale = c.terrain_scale.max(1.0);
+        let t = value_noise(
+            self.seed,
+            LAYER_TERRAIN,
+            id.0 as f32 / scale,
+            id.1 as f32 / scale,
+        );
+        if t > c.wall_threshold {
+            Terrain::Wall
+        } else if t < -c.conductor_threshold {
+            Terrain::Conductor
+        } else {
+            Terrain::Normal
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Biome {
+    Frozen,
+    Cold,
+    Temperate,
+    Hot,
+    Scorched,
+    /// rare spot far hotter than around
+    Hotspot,
+}
+
+impl Biome {
+    fn max_length(self, climate: &Climate) -> usize {
+        let l = &climate.length;
+        match self {
+            Biome::Frozen => l.frozen,
+            Biome::Cold => l.cold,
+            Biome::Temperate => l.temperate,
+            Biome::Hot => l.hot,
+            Biome::Scorched => l.scorched,
+            Biome::Hotspot => l.hotspot,
+        }
+    }
+}
+
+// noise layers, so the fields of a seed are independent of each other
+const LAYER_CLIMATE: u64 = 1;
+const LAYER_HOTSPOT: u64 = 2;
+const LAYER_TERRAIN: u64 = 3;
+
+/// Mean temperature and biome of the node, before the cells scatter around the mean.
+pub fn climate_of(seed: u64, climate: &Climate, id: NodeID) -> (f32, Biome) {
+    let (x, y) = (id.0 as f32, id.1 as f32);
+    let hotspot = value_noise(
+        seed,
+        LAYER_HOTSPOT,
+        x / climate.hotspot_scale.max(1.0),
+        y / climate.hotspot_scale.max(1.0),
+    );
+    // value noise is in -1..1, take it into 0..1 against the threshold
+    if (hotspot + 1.0) / 2.0 > climate.hotspot_threshold {
+        return (climate.hotspot_temperature as f32, Biome::Hotspot);
+    }
+    let t = fbm(seed, LAYER_CLIMATE, x, y, climate);
+    let biome = match t {
+        t if t < -0.35 => Biome::Frozen,
+        t if t < -0.1 => Biome::Cold,
+        t if t < 0.1 => Biome::Temperate,
+        t if t < 0.35 => Biome::Hot,
+        _ => Biome::Scorched,
+    };
+    (t * climate.amplitude, biome)
+}
+
+fn climate(seed: u64, climate: &Climate, id: NodeID) -> NodeData {
+    let (mean, biome) = climate_of(seed, climate, id);
+    let mut rng = node_rng(seed, id);
+    let max = biome.max_length(climate).clamp(1, NODE_MAX_SIZE);
+    let length = max / 4 + rng.next_u32() as usize % (max - max / 4);
+    let spread = climate.spread.clamp(0, i8::MAX as i16) as i32;
+    let data = (0..length)
+        .map(|_| {
+            let offset = (rng.next_u32() % (2 * spread as u32 + 1)) as i32 - spread;
+            let cell = (mean.round() as i32 + offset).clamp(i8::MIN as i32, i8::MAX as i32);
+            cell as i8 as u8
+        })
+        .collect();
+    NodeData::from_bytes(data)
+}
+
+/// Fractal noise of the layer in -1..1, octaves of value noise summed and normalized.
+fn fbm(seed: u64, layer: u64, x: f32, y: f32, climate: &Climate) -> f32 {
+    let (mut sum, mut norm) = (0.0, 0.0);
+    let (mut frequency, mut weight) = (1.0 / climate.scale.max(1.0), 1.0);
+    for octave in 0..climate.octaves.max(1) as u64 {
+        sum += weight * value_noise(seed, layer << 8 | octave, x * frequency, y * frequency);
+        norm += weight;
+        frequency *= 2.0;
+        weight *= climate.persistence;
+    }
+    sum / norm
+}
+
+/// Value noise in -1..1, random values on the integer lattice smoothly interpolated.
+fn value_noise(seed: u64, layer: u64, x: f32, y: f32) -> f32 {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (smooth(x - x0), smooth(y - y0));
+    let (x0, y0) = (x0 as i64, y0 as i64);
+    let at = |dx: i64, dy: i64| lattice(seed, layer, x0 + dx, y0 + dy);
+    let top = at(0, 0) + (at(1, 0) - at(0, 0)) * fx;
+    let bottom = at(0, 1) + (at(1, 1) - at(0, 1)) * fx;
+    top + (bottom - top) * fy
+}
+
+fn smooth(t: f32) -> f32 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Random value in -1..1 of a lattice point.
+fn lattice(seed: u64, layer: u64, x: i64, y: i64) -> f32 {
+    let h = splitmix(seed ^ splitmix(layer ^ splitmix((x as u64) << 32 ^ (y as u32 as u64))));
+    (h >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+}
+
+fn splitmix(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
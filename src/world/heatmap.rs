@@ -0,0 +1,248 @@
+use std::{io::Write, pin::pin};
+
+use clap::ValueEnum;
+use flate2::{write::ZlibEncoder, Compression};
+use futures::TryStreamExt;
+use sea_orm::{ConnectionTrait, EntityTrait, QueryFilter, StreamTrait};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use super::check_region;
+use crate::{
+    entity::{
+        guest,
+        node::{self, mean_of},
+        region::Region,
+    },
+    err::OperationError,
+};
+use entropy_base::grid::NodeID;
+
+/// Most pixels along a side of a heatmap.
+pub const MAX_SIDE: i64 = 4096;
+
+/// Nodes not generated yet.
+const UNKNOWN: [u8; 3] = [0, 0, 0];
+/// Nodes without cells.
+const EMPTY: [u8; 3] = [128, 128, 128];
+/// Nodes with guests on.
+const GUEST: [u8; 3] = [0, 255, 0];
+
+/// Which temperature of the cells colours a node.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum Shade {
+    #[default]
+    Mean,
+    Min,
+    Max,
+}
+
+impl Shade {
+    fn of(self, data: &[u8]) -> Option<f32> {
+        let cells = data.iter().map(|c| *c as i8);
+        match self {
+            Shade::Mean => mean_of(data),
+            Shade::Min => cells.min().map(f32::from),
+            Shade::Max => cells.max().map(f32::from),
+        }
+    }
+}
+
+/// Render the region into a PNG, a block of `scale` by `scale` pixels a node,
+/// x grows rightward and y downward.
+///
+/// Cold is blue, 0 is white and hot is red, nodes not generated are black and
+/// nodes without cells gray, a green square marks the nodes with guests on.
+#[instrument(skip(db), err)]
+pub async fn render<C>(
+    db: &C,
+    region: Region,
+    shade: Shade,
+    scale: u16,
+) -> Result<Vec<u8>, OperationError>
+where
+    C: ConnectionTrait + StreamTrait + Send,
+{
+    check_region(region)?;
+    let scale = scale.max(1) as usize;
+    let width = (region.x1 as i64 - region.x0 as i64 + 1) * scale as i64;
+    let height = (region.y1 as i64 - region.y0 as i64 + 1) * scale as i64;
+    if width > MAX_SIDE || height > MAX_SIDE {
+        return Err(OperationError::HeatmapTooLarge {
+            width,
+            height,
+            max: MAX_SIDE,
+        });
+    }
+    let mut canvas = Canvas::new(width as usize, height as usize);
+    let origin = |id: NodeID| {
+        (
+            (id.0 as i64 - region.x0 as i64) as usize * scale,
+            (id.1 as i64 - region.y0 as i64) as usize * scale,
+        )
+    };
+
+    let mut nodes = pin!(
+        node::Entity::find()
+            .filter(region.condition(node::Column::Id))
+            .stream(db)
+            .await?
+    );
+    while let Some(n) = nodes.try_next().await? {
+        let color = shade.of(&n.data).map_or(EMPTY, color_of);
+        canvas.fill(origin(NodeID::from_i32(n.id)), scale, color);
+    }
+
+    // a quarter of the block is left around the marker, so the node stays visible
+    let inset = scale / 4;
+    let mut guests = pin!(
+        guest::Entity::find()
+            .filter(region.condition(guest::Column::Pos))
+            .stream(db)
+            .await?
+    );
+    while let Some(g) = guests.try_next().await? {
+        let (x, y) = origin(NodeID::from_i32(g.pos));
+        canvas.fill((x + inset, y + inset), scale - 2 * inset, GUEST);
+    }
+
+    // compressing a large canvas takes a while, keep it off the async workers
+    match tokio::task::spawn_blocking(move || canvas.encode_png()).await {
+        Ok(png) => Ok(png),
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
+/// Blue through white to red, saturated at the ends of the cell range.
+fn color_of(temperature: f32) -> [u8; 3] {
+    let k = (temperature.abs() / 128.0).min(1.0);
+    let fade = (255.0 * (1.0 - k)).round() as u8;
+    if temperature < 0.0 {
+        [fade, fade, 255]
+    } else {
+        [255, fade, fade]
+    }
+}
+
+/// RGB pixels, row by row.
+struct Canvas {
+    width: usize,
+    height: usize,
+    rgb: Vec<u8>,
+}
+
+impl Canvas {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            rgb: UNKNOWN.repeat(width * height),
+        }
+    }
+
+    fn fill(&mut self, (x, y): (usize, usize), side: usize, color: [u8; 3]) {
+        for row in y..(y + side).min(self.height) {
+            for col in x..(x + side).min(self.width) {
+                let at = (row * self.width + col) * 3;
+                self.rgb[at..at + 3].copy_from_slice(&color);
+            }
+        }
+    }
+
+    /// 8 bit RGB, no filter, zlib compressed in a single IDAT.
+    fn encode_png(&self) -> Vec<u8> {
+        let mut header = Vec::with_capacity(13);
+        header.extend((self.width as u32).to_be_bytes());
+        header.extend((self.height as u32).to_be_bytes());
+        // bit depth, colour type RGB, compression, filter, no interlace
+        header.extend([8, 2, 0, 0, 0]);
+
+        let mut z = ZlibEncoder::new(Vec::new(), Compression::default());
+        for row in self.rgb.chunks(self.width * 3) {
+            // writing into memory never fails
+            z.write_all(&[0]).expect("write into memory");
+            z.write_all(row).expect("write into memory");
+        }
+        let data = z.finish().expect("write into memory");
+
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        chunk(&mut png, b"IHDR", &header);
+        chunk(&mut png, b"IDAT", &data);
+        chunk(&mut png, b"IEND", &[]);
+        png
+    }
+}
+
+fn chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend((data.len() as u32).to_be_bytes());
+    png.extend(kind);
+    png.extend(data);
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(kind);
+    crc.update(data);
+    png.extend(crc.finalize().to_be_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use flate2::read::ZlibDecoder;
+
+    use super::*;
+
+    #[test]
+    fn png_decodable() {
+        let mut canvas = Canvas::new(3, 2);
+        canvas.fill((1, 0), 1, GUEST);
+        canvas.fill((0, 1), 2, color_of(-128.0));
+        let png = canvas.encode_png();
+
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        let mut chunks = Vec::new();
+        let mut at = 8;
+        while at < png.len() {
+            let len = u32::from_be_bytes(png[at..at + 4].try_into().unwrap()) as usize;
+            let kind = &png[at + 4..at + 8];
+            let data = &png[at + 8..at + 8 + len];
+            let crc = u32::from_be_bytes(png[at + 8 + len..at + 12 + len].try_into().unwrap());
+            assert_eq!(crc, crc32fast::hash(&png[at + 4..at + 8 + len]));
+            chunks.push((kind.to_vec(), data.to_vec()));
+            at += 12 + len;
+        }
+        assert_eq!(at, png.len());
+        let kinds: Vec<_> = chunks.iter().map(|(k, _)| k.as_slice()).collect();
+        assert_eq!(kinds, [b"IHDR", b"IDAT", b"IEND"]);
+
+        let header = &chunks[0].1;
+        assert_eq!(header[..4], 3u32.to_be_bytes());
+        assert_eq!(header[4..8], 2u32.to_be_bytes());
+        assert_eq!(header[8..], [8, 2, 0, 0, 0]);
+
+        let mut raw = Vec::new();
+        ZlibDecoder::new(chunks[1].1.as_slice())
+            .read_to_end(&mut raw)
+            .unwrap();
+        let blue = color_of(-128.0);
+        let expected: Vec<u8> = [
+            [0].as_slice(),
+            &UNKNOWN,
+            &GUEST,
+            &UNKNOWN,
+            &[0],
+            &blue,
+            &blue,
+            &UNKNOWN,
+        ]
+        .concat();
+        assert_eq!(raw, expected);
+    }
+
+    #[test]
+    fn colors_at_the_ends() {
+        assert_eq!(color_of(-128.0), [0, 0, 255]);
+        assert_eq!(color_of(0.0), [255, 255, 255]);
+        assert_eq!(color_of(127.0), [255, 2, 2]);
+    }
+}
@@ -0,0 +1,270 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use chrono::Utc;
+use sea_orm::{sea_query::OnConflict, ConnectionTrait, DbConn, EntityTrait, Set, TransactionTrait};
+use serde::Deserialize;
+use tracing::{info, instrument};
+
+use super::{check_region, flat, WorldGenerator};
+use crate::{
+    config::Flat,
+    entity::{
+        node::{self, Terrain},
+        region::Region,
+        spawn,
+    },
+    err::RuntimeError,
+};
+use entropy_base::grid::{NodeData, NodeID, NODE_MAX_SIZE};
+
+/// Nodes written in one statement on import.
+const IMPORT_BATCH: usize = 256;
+
+/// A map file, in toml.
+///
+/// ```toml
+/// region = { x0 = -8, y0 = -8, x1 = 8, y1 = 8 } # nodes imported, all the nodes designed if omitted
+/// spawn = [[4, 4], [-4, -4]] # where the first guests of players spawn on import
+///
+/// [fill] # nodes not designed
+/// length = 256
+/// temperature = 0
+///
+/// [[node]]
+/// at = [0, 0]
+/// length = 512      # the fill length if omitted
+/// temperature = 100 # the fill temperature if omitted
+///
+/// [[node]]
+/// at = [1, 0]
+/// file = "arena/1_0.bin" # raw cells, relative to the map file
+/// terrain = "wall"       # "normal" if omitted
+/// ```
+#[derive(Debug, Deserialize)]
+struct MapFile {
+    region: Option<Region>,
+    #[serde(default)]
+    spawn: Vec<NodeID>,
+    #[serde(default)]
+    fill: Flat,
+    #[serde(default, rename = "node")]
+    nodes: Vec<MapNode>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MapNode {
+    at: NodeID,
+    length: Option<usize>,
+    temperature: Option<i8>,
+    file: Option<String>,
+    #[serde(default)]
+    terrain: Terrain,
+}
+
+/// Nodes designed in a map file, the rest flat as the map fills.
+#[derive(Debug)]
+pub struct Map {
+    pub region: Option<Region>,
+    pub spawn: Vec<NodeID>,
+    pub fill: Flat,
+    pub nodes: HashMap<NodeID, NodeData>,
+    /// nodes of terrain other than normal
+    pub terrain: HashMap<NodeID, Terrain>,
+}
+
+impl Map {
+    pub fn load(path: &Path) -> Result<Map, RuntimeError> {
+        let read_err = |p: &Path, e| RuntimeError::Map(format!("{} <- {e}", p.display()));
+        let text = fs::read_to_string(path).map_err(|e| read_err(path, e))?;
+        let file: MapFile = toml::from_str(&text)?;
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let mut nodes = HashMap::with_capacity(file.nodes.len());
+        let mut terrain = HashMap::new();
+        for n in file.nodes {
+            let data = match n.file {
+                Some(cells) => {
+                    let cells_path = dir.join(&cells);
+                    let bytes = fs::read(&cells_path).map_err(|e| read_err(&cells_path, e))?;
+                    if bytes.len() >= NODE_MAX_SIZE {
+                        return Err(RuntimeError::Map(format!(
+                            "{cells} of node {:?} has {} cells, max {}",
+                            n.at,
+                            bytes.len(),
+                            NODE_MAX_SIZE - 1
+                        )));
+                    }
+                    NodeData::from_bytes(bytes)
+                }
+                None => flat(Flat {
+                    length: n.length.unwrap_or(file.fill.length),
+                    temperature: n.temperature.unwrap_or(file.fill.temperature),
+                }),
+            };
+            if nodes.insert(n.at, data).is_some() {
+                return Err(RuntimeError::Map(format!("node {:?} designed twice", n.at)));
+            }
+            if n.terrain != Terrain::Normal {
+                terrain.insert(n.at, n.terrain);
+            }
+        }
+        Ok(Map {
+            region: file.region,
+            spawn: file.spawn,
+            fill: file.fill,
+            nodes,
+            terrain,
+        })
+    }
+
+    /// Nodes to import, the region of the map or around all the nodes designed and the spawn points.
+    pub fn import_region(&self) -> Option<Region> {
+        self.region.or_else(|| {
+            let mut ids = self.nodes.keys().chain(self.spawn.iter());
+            let first = *ids.next()?;
+            Some(ids.fold(Region::new(first, first), |r, id| Region {
+                x0: r.x0.min(id.0),
+                y0: r.y0.min(id.1),
+                x1: r.x1.max(id.0),
+                y1: r.y1.max(id.1),
+            }))
+        })
+    }
+
+    /// Write every node of the import region into the world, replacing the existing ones,
+    /// and make the spawn points of the map the only ones.
+    ///
+    /// Return the number of nodes written.
+    #[instrument(skip(self, db), err)]
+    pub async fn import(&self, db: &DbConn) -> Result<u64, RuntimeError> {
+        let region = self
+            .import_region()
+            .ok_or(RuntimeError::Map("nothing to import".to_string()))?;
+        check_region(region).map_err(|e| RuntimeError::Map(e.to_string()))?;
+        for p in &self.spawn {
+            check_region(Region::new(*p, *p)).map_err(|e| RuntimeError::Map(e.to_string()))?;
+        }
+
+        let total =
+            (region.x1 as i64 - region.x0 as i64 + 1) * (region.y1 as i64 - region.y0 as i64 + 1);
+        info!("importing {total} nodes of {region:?}");
+        let txn = db.begin().await?;
+        let mut batch = Vec::with_capacity(IMPORT_BATCH);
+        let mut written = 0;
+        for x in region.x0..=region.x1 {
+            for y in region.y0..=region.y1 {
+                let id = NodeID(x, y);
+                batch.push(node::ActiveModel {
+                    id: Set(id.into_i32()),
+                    data: Set(self.generate(id).into()),
+                    terrain: Set(self.terrain(id)),
+                    touched_at: Set(Utc::now()),
+                    imported: Set(true),
+                });
+                if batch.len() == IMPORT_BATCH {
+                    written += write_batch(&txn, &mut batch).await?;
+                    info!("imported {written}/{total} nodes");
+                }
+            }
+        }
+        written += write_batch(&txn, &mut batch).await?;
+        spawn::replace(&txn, &self.spawn).await?;
+        txn.commit().await?;
+        info!(
+            "imported {written} nodes and {} spawn points",
+            self.spawn.len()
+        );
+        Ok(written)
+    }
+}
+
+async fn write_batch<C: ConnectionTrait>(
+    db: &C,
+    batch: &mut Vec<node::ActiveModel>,
+) -> Result<u64, RuntimeError> {
+    if batch.is_empty() {
+        return Ok(0);
+    }
+    let n = batch.len() as u64;
+    node::Entity::insert_many(batch.drain(..))
+        .on_conflict(
+            OnConflict::column(node::Column::Id)
+                .update_columns([
+                    node::Column::Data,
+                    node::Column::Terrain,
+                    node::Column::TouchedAt,
+                    node::Column::Imported,
+                ])
+                .to_owned(),
+        )
+        .exec_without_returning(db)
+        .await?;
+    Ok(n)
+}
+
+impl WorldGenerator for Map {
+    fn generate(&self, id: NodeID) -> NodeData {
+        match self.nodes.get(&id) {
+            Some(data) => data.clone(),
+            None => flat(self.fill),
+        }
+    }
+
+    fn terrain(&self, id: NodeID) -> Terrain {
+        self.terrain.get(&id).copied().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn designed_nodes_and_fill() {
+        let dir = std::env::temp_dir().join(format!("entropy-map-{}", std::process::id()));
+        fs::create_dir_all(dir.join("arena")).unwrap();
+        fs::write(dir.join("arena/1_0.bin"), [1u8, 2, 3]).unwrap();
+        let path = dir.join("map.toml");
+        fs::write(
+            &path,
+            r#"
+spawn = [[-2, 3]]
+
+[fill]
+length = 4
+temperature = 7
+
+[[node]]
+at = [0, 0]
+temperature = 100
+
+[[node]]
+at = [1, 0]
+file = "arena/1_0.bin"
+terrain = "wall"
+"#,
+        )
+        .unwrap();
+        let map = Map::load(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(map.generate(NodeID(0, 0)).to_bytes(), vec![100; 4]);
+        assert_eq!(map.generate(NodeID(1, 0)).to_bytes(), vec![1, 2, 3]);
+        assert_eq!(map.generate(NodeID(5, 5)).to_bytes(), vec![7; 4]);
+        assert_eq!(map.terrain(NodeID(1, 0)), Terrain::Wall);
+        assert_eq!(map.terrain(NodeID(0, 0)), Terrain::Normal);
+        assert_eq!(
+            map.import_region(),
+            Some(Region::new(NodeID(-2, 0), NodeID(1, 3)))
+        );
+    }
+
+    #[test]
+    fn node_designed_twice() {
+        let path =
+            std::env::temp_dir().join(format!("entropy-map-twice-{}.toml", std::process::id()));
+        fs::write(&path, "[[node]]\nat = [0, 0]\n\n[[node]]\nat = [0, 0]\n").unwrap();
+        let map = Map::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(map, Err(RuntimeError::Map(_))));
+    }
+}
@@ -0,0 +1,409 @@
+use std::sync::OnceLock;
+
+use rand::{rngs::SmallRng, RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::{
+    config::{self, Edge, Flat, Generator},
+    entity::{node::Terrain, region::Region},
+    err::{OperationError, RuntimeError},
+};
+use entropy_base::grid::{navi, NodeData, NodeID, NODE_MAX_SIZE};
+
+pub mod climate;
+pub mod heatmap;
+pub mod map;
+pub mod pregen;
+
+/// Where the data of new nodes comes from.
+pub trait WorldGenerator: Send + Sync {
+    /// Data of the node, the same every time for the same node unless the generator is random.
+    fn generate(&self, id: NodeID) -> NodeData;
+
+    /// Whether the data of a node is the same every time, nodes only regenerate if so.
+    fn deterministic(&self) -> bool {
+        true
+    }
+
+    /// Terrain of the node, normal unless the generator says otherwise.
+    fn terrain(&self, _id: NodeID) -> Terrain {
+        Terrain::Normal
+    }
+}
+
+static GENERATOR: OnceLock<Box<dyn WorldGenerator>> = OnceLock::new();
+
+/// Install the generator selected by `rule.world.generator`, only the first call takes effect.
+///
+/// Call after the rules are installed, the map of the `map` generator is loaded here.
+pub fn init_generator() -> Result<(), RuntimeError> {
+    let world = &config::rule().world;
+    let generator: Box<dyn WorldGenerator> = match world.generator {
+        Generator::Uniform => Box::new(UniformGenerator { seed: world.seed }),
+        Generator::Random => Box::new(RandomGenerator),
+        Generator::Climate => Box::new(climate::ClimateGenerator {
+            seed: world.seed,
+            climate: &world.climate,
+        }),
+        Generator::Map => Box::new(map::Map::load(&world.map)?),
+        Generator::Flat => Box::new(FlatGenerator(world.flat)),
+    };
+    let _ = GENERATOR.set(generator);
+    Ok(())
+}
+
+/// Generator installed by [`init_generator`], or the uniform generator of the seed.
+pub fn generator() -> &'static dyn WorldGenerator {
+    GENERATOR
+        .get_or_init(|| {
+            Box::new(UniformGenerator {
+                seed: config::rule().world.seed,
+            })
+        })
+        .as_ref()
+}
+
+/// Data of a new node.
+pub fn generate(id: NodeID) -> NodeData {
+    generator().generate(id)
+}
+
+/// Terrain of a new node, the origin is always normal so that guests can spawn and leave.
+pub fn terrain(id: NodeID) -> Terrain {
+    if id == NodeID::ORIGIN {
+        return Terrain::Normal;
+    }
+    generator().terrain(id)
+}
+
+/// Nodes of the world.
+pub fn bounds() -> Region {
+    let b = &config::rule().world.bounds;
+    Region::new(NodeID(b.x0, b.y0), NodeID(b.x1, b.y1))
+}
+
+/// The node itself in the world, its counterpart on a wrapped world,
+/// or an error on a walled world.
+pub fn locate(id: NodeID) -> Result<NodeID, OperationError> {
+    place(id.0 as i32, id.1 as i32).ok_or(OperationError::NodeOutOfBounds(id))
+}
+
+/// The node one step from `from`, across the edge on a wrapped world.
+pub fn step(from: NodeID, to: navi::Direction) -> Result<NodeID, OperationError> {
+    place(from.0 as i32 + to.0 as i32, from.1 as i32 + to.1 as i32)
+        .ok_or(OperationError::WalkOutOfBounds { from, to })
+}
+
+/// Error if the region reaches out of the world, regions are never wrapped.
+pub fn check_region(region: Region) -> Result<(), OperationError> {
+    let b = bounds();
+    for (x, y) in [(region.x0, region.y0), (region.x1, region.y1)] {
+        if !(b.x0..=b.x1).contains(&x) || !(b.y0..=b.y1).contains(&y) {
+            return Err(OperationError::NodeOutOfBounds(NodeID(x, y)));
+        }
+    }
+    Ok(())
+}
+
+/// Nodes at most `radius` from the center, moving diagonally counts as one,
+/// split across the edges on a wrapped world and cut at the walls otherwise.
+pub fn around(center: NodeID, radius: i16) -> Vec<Region> {
+    let r = radius.max(0) as i32;
+    let (x, y) = (center.0 as i32, center.1 as i32);
+    split(
+        bounds(),
+        config::rule().world.bounds.edge,
+        (x - r, x + r),
+        (y - r, y + r),
+    )
+}
+
+/// The rectangle `xs` by `ys`, which may reach beyond the bounds, as a region of the world:
+/// cut at the walls, or with its corners placed in the world on a wrapped world,
+/// where a corner past the other means the region crosses the edge.
+pub fn fit_region(xs: (i32, i32), ys: (i32, i32)) -> Region {
+    fit_in(bounds(), config::rule().world.bounds.edge, xs, ys)
+}
+
+/// Nodes of the world inside the region, which may reach beyond the bounds,
+/// split across the edges on a wrapped world and cut at the walls otherwise.
+/// A region from [`fit_region`] crossing the edge is split the same way.
+pub fn split_region(region: Region) -> Vec<Region> {
+    split(
+        bounds(),
+        config::rule().world.bounds.edge,
+        (region.x0 as i32, region.x1 as i32),
+        (region.y0 as i32, region.y1 as i32),
+    )
+}
+
+/// Distance between two nodes of the world, moving diagonally counts as one,
+/// the shorter way round on a wrapped world.
+pub fn distance(one: NodeID, other: NodeID) -> i32 {
+    let b = bounds();
+    let wrapped = config::rule().world.bounds.edge == Edge::Wrap;
+    let axis = |a: i16, b0: i16, b1: i16, o: i16| {
+        let d = (a as i32 - o as i32).abs();
+        let width = b1 as i32 - b0 as i32 + 1;
+        if wrapped {
+            d.min(width - d)
+        } else {
+            d
+        }
+    };
+    axis(one.0, b.x0, b.x1, other.0).max(axis(one.1, b.y0, b.y1, other.1))
+}
+
+fn place(x: i32, y: i32) -> Option<NodeID> {
+    place_in(bounds(), config::rule().world.bounds.edge, x, y)
+}
+
+fn place_in(b: Region, edge: Edge, x: i32, y: i32) -> Option<NodeID> {
+    let (xs, ys) = (b.x0 as i32..=b.x1 as i32, b.y0 as i32..=b.y1 as i32);
+    if xs.contains(&x) && ys.contains(&y) {
+        return Some(NodeID(x as i16, y as i16));
+    }
+    match edge {
+        Edge::Wall => None,
+        Edge::Wrap => Some(NodeID(wrap(x, b.x0, b.x1), wrap(y, b.y0, b.y1))),
+    }
+}
+
+fn fit_in(b: Region, edge: Edge, xs: (i32, i32), ys: (i32, i32)) -> Region {
+    let fit = |(lo, hi): (i32, i32), b0: i16, b1: i16| match edge {
+        Edge::Wall => (
+            lo.clamp(b0 as i32, b1 as i32) as i16,
+            hi.clamp(b0 as i32, b1 as i32) as i16,
+        ),
+        Edge::Wrap if hi - lo >= b1 as i32 - b0 as i32 => (b0, b1),
+        Edge::Wrap => (wrap(lo, b0, b1), wrap(hi, b0, b1)),
+    };
+    let ((x0, x1), (y0, y1)) = (fit(xs, b.x0, b.x1), fit(ys, b.y0, b.y1));
+    Region { x0, y0, x1, y1 }
+}
+
+fn split(b: Region, edge: Edge, xs: (i32, i32), ys: (i32, i32)) -> Vec<Region> {
+    let xs = split_axis(xs, b.x0, b.x1, edge);
+    let ys = split_axis(ys, b.y0, b.y1, edge);
+    xs.iter()
+        .flat_map(|&(x0, x1)| ys.iter().map(move |&(y0, y1)| Region { x0, y0, x1, y1 }))
+        .collect()
+}
+
+/// Ranges of the axis `lo..=hi` covers within `b0..=b1`, at most two.
+fn split_axis((lo, hi): (i32, i32), b0: i16, b1: i16, edge: Edge) -> Vec<(i16, i16)> {
+    let (b0i, b1i) = (b0 as i32, b1 as i32);
+    match edge {
+        Edge::Wall if lo.max(b0i) > hi.min(b1i) => vec![],
+        Edge::Wall => vec![(lo.max(b0i) as i16, hi.min(b1i) as i16)],
+        Edge::Wrap if hi - lo >= b1i - b0i => vec![(b0, b1)],
+        Edge::Wrap => {
+            let (lo, hi) = (wrap(lo, b0, b1), wrap(hi, b0, b1));
+            if lo <= hi {
+                vec![(lo, hi)]
+            } else {
+                vec![(lo, b1), (b0, hi)]
+            }
+        }
+    }
+}
+
+fn wrap(v: i32, lo: i16, hi: i16) -> i16 {
+    let width = hi as i32 - lo as i32 + 1;
+    (lo as i32 + (v - lo as i32).rem_euclid(width)) as i16
+}
+
+/// Every node draws from its own stream of the seeded ChaCha8,
+/// which is reproducible across platforms and versions, unlike `SmallRng`.
+fn node_rng(seed: u64, id: NodeID) -> ChaCha8Rng {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    rng.set_stream(id.into_i32() as u32 as u64);
+    rng
+}
+
+/// Uniform random cells of uniform random length, from the stream of the node.
+pub struct UniformGenerator {
+    pub seed: u64,
+}
+
+impl WorldGenerator for UniformGenerator {
+    fn generate(&self, id: NodeID) -> NodeData {
+        uniform(&mut node_rng(self.seed, id))
+    }
+}
+
+/// Uniform random cells of uniform random length, different every time.
+pub struct RandomGenerator;
+
+impl WorldGenerator for RandomGenerator {
+    fn generate(&self, _id: NodeID) -> NodeData {
+        uniform(&mut SmallRng::from_entropy())
+    }
+
+    fn deterministic(&self) -> bool {
+        false
+    }
+}
+
+fn uniform(rng: &mut impl RngCore) -> NodeData {
+    // NODE_MAX_SIZE divides 2^32, so the length is uniform
+    let length = rng.next_u32() as usize % NODE_MAX_SIZE;
+    let mut data = vec![0u8; length];
+    rng.fill_bytes(&mut data);
+    NodeData::from_bytes(data)
+}
+
+/// Every node alike.
+pub struct FlatGenerator(pub Flat);
+
+impl WorldGenerator for FlatGenerator {
+    fn generate(&self, _id: NodeID) -> NodeData {
+        flat(self.0)
+    }
+}
+
+pub fn flat(f: Flat) -> NodeData {
+    let length = f.length.min(NODE_MAX_SIZE - 1);
+    NodeData::from_bytes(vec![f.temperature as u8; length])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_node() {
+        let generator = UniformGenerator { seed: 42 };
+        for id in [NodeID(0, 0), NodeID(-3, 7), NodeID(i16::MAX, i16::MIN)] {
+            assert_eq!(generator.generate(id), generator.generate(id));
+            assert_eq!(
+                generator.generate(id),
+                UniformGenerator { seed: 42 }.generate(id)
+            );
+        }
+    }
+
+    #[test]
+    fn different_node_or_seed_different_data() {
+        let generator = UniformGenerator { seed: 42 };
+        assert_ne!(
+            generator.generate(NodeID(1, 0)),
+            generator.generate(NodeID(0, 1))
+        );
+        assert_ne!(
+            generator.generate(NodeID(1, 0)),
+            UniformGenerator { seed: 43 }.generate(NodeID(1, 0))
+        );
+    }
+
+    #[test]
+    fn flat_nodes_alike() {
+        let generator = FlatGenerator(Flat {
+            length: 16,
+            temperature: -5,
+        });
+        let data = generator.generate(NodeID(3, -2));
+        assert_eq!(data, generator.generate(NodeID(0, 0)));
+        assert_eq!(data.to_bytes(), vec![-5i8 as u8; 16]);
+        assert_eq!(generator.terrain(NodeID(3, -2)), Terrain::Normal);
+    }
+
+    #[test]
+    fn flat_within_max_size() {
+        let data = flat(Flat {
+            length: NODE_MAX_SIZE * 2,
+            temperature: 0,
+        });
+        assert_eq!(data.to_bytes().len(), NODE_MAX_SIZE - 1);
+    }
+
+    #[test]
+    fn wrap_at_bounds() {
+        assert_eq!(wrap(-10, -10, 9), -10);
+        assert_eq!(wrap(9, -10, 9), 9);
+        assert_eq!(wrap(10, -10, 9), -10);
+        assert_eq!(wrap(-11, -10, 9), 9);
+        assert_eq!(wrap(i16::MAX as i32 + 1, i16::MIN, i16::MAX), i16::MIN);
+        assert_eq!(wrap(i16::MIN as i32 - 1, i16::MIN, i16::MAX), i16::MAX);
+    }
+
+    #[test]
+    fn place_at_bounds() {
+        let b = Region::new(NodeID(-2, -3), NodeID(2, 3));
+        assert_eq!(place_in(b, Edge::Wall, 2, -3), Some(NodeID(2, -3)));
+        assert_eq!(place_in(b, Edge::Wall, 3, 0), None);
+        assert_eq!(place_in(b, Edge::Wall, 0, -4), None);
+        assert_eq!(place_in(b, Edge::Wrap, 2, 3), Some(NodeID(2, 3)));
+        assert_eq!(place_in(b, Edge::Wrap, 3, 0), Some(NodeID(-2, 0)));
+        assert_eq!(place_in(b, Edge::Wrap, -3, 4), Some(NodeID(2, -3)));
+    }
+
+    #[test]
+    fn split_across_edges() {
+        let b = Region::new(NodeID(-2, -3), NodeID(2, 3));
+        assert_eq!(
+            split(b, Edge::Wall, (1, 4), (-1, 1)),
+            vec![Region::new(NodeID(1, -1), NodeID(2, 1))]
+        );
+        assert_eq!(split(b, Edge::Wall, (3, 4), (-1, 1)), vec![]);
+        assert_eq!(
+            split(b, Edge::Wrap, (1, 3), (-4, -3)),
+            vec![
+                Region::new(NodeID(1, 3), NodeID(2, 3)),
+                Region::new(NodeID(1, -3), NodeID(2, -3)),
+                Region::new(NodeID(-2, 3), NodeID(-2, 3)),
+                Region::new(NodeID(-2, -3), NodeID(-2, -3)),
+            ]
+        );
+        assert_eq!(
+            split(b, Edge::Wrap, (-9, 9), (0, 0)),
+            vec![Region::new(NodeID(-2, 0), NodeID(2, 0))]
+        );
+    }
+
+    #[test]
+    fn fit_across_edges() {
+        let b = Region::new(NodeID(-2, -3), NodeID(2, 3));
+        assert_eq!(
+            fit_in(b, Edge::Wall, (1, 4), (-5, 1)),
+            Region::new(NodeID(1, -3), NodeID(2, 1))
+        );
+        assert_eq!(
+            fit_in(b, Edge::Wrap, (1, 3), (-4, -3)),
+            Region {
+                x0: 1,
+                y0: 3,
+                x1: -2,
+                y1: -3
+            }
+        );
+        assert_eq!(
+            split(b, Edge::Wrap, (1, -2), (3, -3)),
+            split(b, Edge::Wrap, (1, 3), (-4, -3))
+        );
+        let full = Region::new(NodeID(i16::MIN, i16::MIN), NodeID(i16::MAX, i16::MAX));
+        assert_eq!(
+            fit_in(
+                full,
+                Edge::Wrap,
+                (i16::MAX as i32, i16::MAX as i32 + 2),
+                (0, 0)
+            ),
+            Region {
+                x0: i16::MAX,
+                y0: 0,
+                x1: i16::MIN + 1,
+                y1: 0
+            }
+        );
+    }
+
+    #[test]
+    fn uniform_within_max_size() {
+        let generator = UniformGenerator { seed: 0 };
+        for x in -8..8 {
+            let data = generator.generate(NodeID(x, x)).to_bytes();
+            assert!(data.len() < NODE_MAX_SIZE);
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use sea_orm::{sea_query::OnConflict, DbConn, EntityTrait, Iterable};
+use tracing::{info, instrument};
+
+use super::check_region;
+use crate::{
+    entity::{node, region::Region},
+    err::RuntimeError,
+};
+use entropy_base::grid::NodeID;
+
+/// Postgres takes at most 65535 parameters a statement.
+const MAX_PARAMS: usize = 65535;
+
+/// Generate every node of the region not generated yet, `batch` nodes an insert.
+///
+/// Every batch is committed on its own, so an interrupted run is simply run again.
+/// Return the number of nodes generated.
+#[instrument(skip(db), err)]
+pub async fn pregen(db: &DbConn, region: Region, batch: usize) -> Result<u64, RuntimeError> {
+    check_region(region)?;
+    // a node binds a parameter for every column
+    let batch = batch.clamp(1, MAX_PARAMS / node::Column::iter().count());
+    let total =
+        (region.x1 as i64 - region.x0 as i64 + 1) * (region.y1 as i64 - region.y0 as i64 + 1);
+    info!("pregenerating {total} nodes of {region:?}");
+    let mut nodes = Vec::with_capacity(batch);
+    let (mut done, mut generated) = (0, 0);
+    for x in region.x0..=region.x1 {
+        for y in region.y0..=region.y1 {
+            nodes.push(node::Model::generate(NodeID(x, y)));
+            if nodes.len() == batch {
+                done += nodes.len() as i64;
+                generated += insert_batch(db, &mut nodes).await?;
+                info!("pregenerated {done}/{total} nodes, {generated} new");
+            }
+        }
+    }
+    generated += insert_batch(db, &mut nodes).await?;
+    info!(
+        "pregenerated {generated} nodes, {} existed",
+        total - generated as i64
+    );
+    Ok(generated)
+}
+
+/// Insert the nodes, skipping the existing ones, return the number inserted.
+async fn insert_batch(
+    db: &DbConn,
+    nodes: &mut Vec<node::ActiveModel>,
+) -> Result<u64, RuntimeError> {
+    if nodes.is_empty() {
+        return Ok(0);
+    }
+    let inserted = node::Entity::insert_many(nodes.drain(..))
+        .on_conflict(OnConflict::column(node::Column::Id).do_nothing().to_owned())
+        .exec_without_returning(db)
+        .await?;
+    Ok(inserted)
+}